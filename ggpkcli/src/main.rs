@@ -1,13 +1,21 @@
-use std::io::Cursor;
+use std::fs::File;
+use std::io::{BufRead, BufWriter, Cursor, Write};
+use std::ops::Range;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use ddsfile::Dds;
-use ggpklib::dat::{DatFile, DatValue};
-use ggpklib::dat_schema::SchemaFile;
-use ggpklib::poefs::{LocalSource, OnlineSource, PoeFS};
+use ggpklib::dat::{column_names, DatFile, DatFormat, DatValue};
+use ggpklib::dat_schema::{ColumnType, SchemaEnumeration, SchemaFile, TableColumn};
+use ggpklib::poefs::{find_install_dir, LocalSource, LooseFilesSource, OnlineSource, PoeFS};
+use ggpklib::utils::decode_utf16;
 
+use anyhow::anyhow;
 use clap::Parser;
 
+/// How long downloaded schema is reused before downloading it again
+const SCHEMA_CACHE_MAX_AGE: Duration = Duration::from_secs(60 * 60 * 24);
+
 #[derive(Debug, Parser)]
 #[clap(group(clap::ArgGroup::new("source").required(true)))]
 struct Args {
@@ -23,13 +31,45 @@ struct Args {
         short,
         long,
         group = "source",
-        help = "Get requested file from patch server"
+        help = "Get requested file from patch server, downloaded files are cached in temporary directory"
     )]
     online: bool,
+    #[arg(
+        long,
+        conflicts_with_all = ["ggpk", "install", "dir"],
+        help = "Patch version to download files of, e.g. '3.25.1.1', defaults to the latest one"
+    )]
+    patch: Option<String>,
+    #[arg(
+        long,
+        conflicts_with_all = ["ggpk", "install", "dir"],
+        help = "Patch server to download files from, defaults to the one of the global realm"
+    )]
+    cdn: Option<String>,
+    #[arg(
+        long,
+        conflicts_with_all = ["ggpk", "install", "dir"],
+        help = "Remove cached files of other patch versions of the patch server"
+    )]
+    prune_cache: bool,
     #[arg(
         short,
         long,
-        help = "Path to schema.json file, only needed if '--ggpk' argument is used"
+        group = "source",
+        help = "Get files from automatically detected game install directory"
+    )]
+    install: bool,
+    #[arg(
+        short,
+        long,
+        group = "source",
+        help = "Get files from game directory with loose Bundles2 directory"
+    )]
+    dir: Option<PathBuf>,
+    #[arg(
+        short,
+        long,
+        help = "Path to schema.json file, required if '--ggpk' argument is used, downloaded otherwise"
     )]
     schema_path: Option<PathBuf>,
     #[command(subcommand)]
@@ -38,22 +78,182 @@ struct Args {
 
 #[derive(Debug, clap::Subcommand)]
 pub enum Command {
+    /// Extracts file and converts it into readable format
     Get {
         file: PathBuf,
-        #[arg(default_value = "output.csv")]
-        output: PathBuf,
+        #[arg(help = "Output file, defaults to 'output' with extension of the format")]
+        output: Option<PathBuf>,
+        #[command(flatten)]
+        dat_options: DatOptions,
+        #[arg(
+            long,
+            value_enum,
+            help = "Compress csv and json output, extension of the compression is appended to output path"
+        )]
+        compress: Option<Compression>,
+        #[arg(
+            long,
+            help = "Save file bytes as they are without converting, files with unsupported extension are always saved as they are"
+        )]
+        raw: bool,
     },
     ListPaths,
+    /// Prints a single row of dat file as column names and values
+    Row {
+        file: PathBuf,
+        index: usize,
+    },
+    /// Prints rows of dat file that contain query in any of string columns, case insensitive
+    Search {
+        file: PathBuf,
+        query: String,
+    },
+    /// Opens file system once and reads commands from standard input, type 'help' to list them
+    Repl,
+}
+
+/// Compression of csv and json output files
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    fn extension(self) -> &'static str {
+        match self {
+            Compression::Gzip => "gz",
+            Compression::Zstd => "zst",
+        }
+    }
+}
+
+/// Creates output file, if `compression` is set its extension is appended to the path and
+/// written data is compressed
+fn create_output(
+    output: &Path,
+    compression: Option<Compression>,
+) -> Result<Box<dyn Write>, anyhow::Error> {
+    let Some(compression) = compression else {
+        return Ok(Box::new(BufWriter::new(File::create(output)?)));
+    };
+    let mut path = output.as_os_str().to_owned();
+    path.push(".");
+    path.push(compression.extension());
+    let file = BufWriter::new(File::create(path)?);
+    Ok(match compression {
+        Compression::Gzip => Box::new(flate2::write::GzEncoder::new(
+            file,
+            flate2::Compression::default(),
+        )),
+        Compression::Zstd => Box::new(zstd::Encoder::new(file, 0)?.auto_finish()),
+    })
+}
+
+/// Output format of dat files
+#[derive(Debug, Clone, Copy, Default, clap::ValueEnum)]
+pub enum OutputFormat {
+    #[default]
+    Csv,
+    /// Array of row objects
+    Json,
+    /// One row object per line
+    Ndjson,
+}
+
+impl OutputFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Csv => "csv",
+            OutputFormat::Json => "json",
+            OutputFormat::Ndjson => "ndjson",
+        }
+    }
+}
+
+/// Options for exporting dat files
+#[derive(Debug, Default, clap::Args)]
+pub struct DatOptions {
+    #[arg(
+        short,
+        long,
+        help = "Resolve localized columns of dat file using given language, e.g. 'French'"
+    )]
+    language: Option<String>,
+    #[arg(
+        short,
+        long,
+        value_delimiter = ',',
+        help = "Export only given columns of dat file in given order, e.g. 'Id,Name'"
+    )]
+    columns: Option<Vec<String>>,
+    #[arg(
+        short,
+        long,
+        value_enum,
+        default_value_t,
+        help = "Format of exported dat file, rows are written as objects keyed by column names in JSON formats"
+    )]
+    format: OutputFormat,
+    #[arg(
+        long,
+        help = "Also write name, type and description of exported dat columns to '<OUTPUT>.schema.json'"
+    )]
+    describe: bool,
+    #[arg(
+        short,
+        long,
+        value_parser = parse_row_range,
+        help = "Export only rows in given range of dat file, e.g. '0..100', '..100' or '100..'"
+    )]
+    rows: Option<Range<usize>>,
+    #[arg(
+        long,
+        help = "Trim whitespace around exported strings and remove control characters other than line breaks and tabs"
+    )]
+    trim: bool,
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "language", "columns", "rows", "trim", "format", "describe", "compress", "output"
+        ],
+        help = "Write whole dat file into table of SQLite database instead of output file, existing table is replaced"
+    )]
+    sqlite: Option<PathBuf>,
+}
+
+/// Parses range like `start..end` where either bound can be omitted
+fn parse_row_range(s: &str) -> Result<Range<usize>, String> {
+    let (start, end) = s
+        .split_once("..")
+        .ok_or_else(|| format!("'{s}' is not a range, expected 'start..end'"))?;
+    let parse_bound = |bound: &str, default| {
+        if bound.is_empty() {
+            Ok(default)
+        } else {
+            bound
+                .parse()
+                .map_err(|e| format!("invalid range bound '{bound}': {e}"))
+        }
+    };
+    Ok(parse_bound(start, 0)?..parse_bound(end, usize::MAX)?)
 }
 
-fn datvalue_to_csv_cell(value: DatValue) -> String {
+/// Converts value to csv cell, values of enum columns are written as enumerator names if
+/// `enumeration` is provided and has a name for the value
+fn datvalue_to_csv_cell(value: DatValue, enumeration: Option<&SchemaEnumeration>) -> String {
     match value {
         DatValue::Bool(b) => b.to_string(),
         DatValue::String(s) => s,
         DatValue::I32(i) => i.to_string(),
+        DatValue::I64(i) => i.to_string(),
+        DatValue::U64(u) => u.to_string(),
         DatValue::F32(f) => f.to_string(),
         DatValue::Array(a) => {
-            let a = a.into_iter().map(datvalue_to_csv_cell).collect::<Vec<_>>();
+            let a = a
+                .into_iter()
+                .map(|value| datvalue_to_csv_cell(value, enumeration))
+                .collect::<Vec<_>>();
             let joined = a.join(";");
             format!("[{joined}]")
         }
@@ -61,54 +261,272 @@ fn datvalue_to_csv_cell(value: DatValue) -> String {
         DatValue::ForeignRow { rid, .. } => {
             format!("{rid:?}")
         }
-        DatValue::EnumRow(r) => r.to_string(),
+        DatValue::EnumRow(r) => enumeration
+            .and_then(|e| e.enumerator_name(r))
+            .map_or_else(|| r.to_string(), str::to_string),
         DatValue::UnknownArray(_, _) => "?".to_string(),
     }
 }
 
+/// Converts value to JSON, values of enum columns are written as enumerator names if
+/// `enumeration` is provided and has a name for the value
+fn datvalue_to_json(value: DatValue, enumeration: Option<&SchemaEnumeration>) -> serde_json::Value {
+    match value {
+        DatValue::EnumRow(r) => match enumeration.and_then(|e| e.enumerator_name(r)) {
+            Some(name) => serde_json::Value::from(name),
+            None => serde_json::Value::from(r),
+        },
+        DatValue::Array(a) => a
+            .into_iter()
+            .map(|value| datvalue_to_json(value, enumeration))
+            .collect(),
+        value => serde_json::to_value(value).unwrap_or_default(),
+    }
+}
+
+/// Saves dat file to csv or JSON, if `localized_bytes` is provided values of localized columns
+/// are taken from it, otherwise headers of localized columns are marked with `[localized]`
 fn save_dat_file(
     bytes: Vec<u8>,
+    localized_bytes: Option<Vec<u8>>,
     schema: &SchemaFile,
     path: impl AsRef<Path>,
     output: impl AsRef<Path>,
+    options: &DatOptions,
+    compression: Option<Compression>,
 ) -> Result<(), anyhow::Error> {
-    let table_name = path.as_ref().file_stem().unwrap().to_str().unwrap();
-    let file_dat = DatFile::new(bytes);
+    let extension = path.as_ref().extension().unwrap().to_str().unwrap();
+    let format = DatFormat::from_extension(extension).unwrap();
+    let file_dat = DatFile::with_format(bytes, format);
+    let localized_dat = localized_bytes.map(|bytes| DatFile::with_format(bytes, format));
+    if let Some(localized_dat) = &localized_dat {
+        if localized_dat.row_count() != file_dat.row_count() {
+            return Err(anyhow!(
+                "localized file has {} rows, expected {}",
+                localized_dat.row_count(),
+                file_dat.row_count()
+            ));
+        }
+    }
 
-    let file_schema = schema.find_table(table_name).unwrap();
+    let file_schema = schema
+        .find_table_for_path(path.as_ref().to_str().unwrap())
+        .ok_or_else(|| anyhow!("table for {} not found in schema", path.as_ref().display()))?;
     let file_columns = &file_schema.columns;
 
-    let mut wtr = csv::Writer::from_path(output)?;
-    let mut unknown_count = 0;
-    let headers = file_columns.iter().map(|c| {
-        c.name.clone().unwrap_or_else(|| {
-            let s = format!("Unknown{unknown_count}");
-            unknown_count += 1;
-            s
-        })
-    });
+    let names = column_names(file_columns);
+    let selected = match &options.columns {
+        Some(columns) => columns
+            .iter()
+            .map(|column| {
+                names.iter().position(|n| n == column).ok_or_else(|| {
+                    anyhow!(
+                        "column '{}' not found, available columns: {}",
+                        column,
+                        names.join(", ")
+                    )
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        None => (0..file_columns.len()).collect(),
+    };
+
+    if options.describe {
+        let description = selected
+            .iter()
+            .map(|&index| {
+                let column = &file_columns[index];
+                serde_json::json!({
+                    "name": names[index],
+                    "type": column.ttype,
+                    "array": column.array,
+                    "description": column.description,
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut description_path = output.as_ref().as_os_str().to_owned();
+        description_path.push(".schema.json");
+        std::fs::write(
+            description_path,
+            serde_json::to_string_pretty(&description)?,
+        )?;
+    }
 
-    wtr.write_record(headers)?;
-    for i in 0..file_dat.row_count() as usize {
+    let enumerations = file_columns
+        .iter()
+        .map(|column| schema.column_enumeration(column))
+        .collect::<Vec<_>>();
+    let row_count = file_dat.row_count() as usize;
+    let rows = match &options.rows {
+        Some(rows) => rows.start.min(row_count)..rows.end.min(row_count),
+        None => 0..row_count,
+    };
+    // values of selected columns of each row, in the order of `selected`
+    let rows = rows.map(|i| {
         let mut row = file_dat.nth_row(i);
-        let values = row.read_with_schema(file_columns);
-        let values = values.into_iter().map(datvalue_to_csv_cell);
-        wtr.write_record(values)?;
+        let mut values = row.read_with_schema(file_columns);
+        if let Some(localized_dat) = &localized_dat {
+            let localized_values = localized_dat.nth_row(i).read_with_schema(file_columns);
+            for (index, column) in file_columns.iter().enumerate() {
+                if column.localized {
+                    values[index] = localized_values[index].clone();
+                }
+            }
+        }
+        selected
+            .iter()
+            .map(|&index| {
+                let value = values[index].clone();
+                if options.trim {
+                    value.normalize_strings(true)
+                } else {
+                    value
+                }
+            })
+            .collect::<Vec<_>>()
+    });
+
+    let mut writer = create_output(output.as_ref(), compression)?;
+    match options.format {
+        OutputFormat::Csv => {
+            let mut wtr = csv::Writer::from_writer(writer);
+            let headers = selected.iter().map(|&index| {
+                if file_columns[index].localized && localized_dat.is_none() {
+                    format!("{} [localized]", names[index])
+                } else {
+                    names[index].clone()
+                }
+            });
+            wtr.write_record(headers)?;
+            for values in rows {
+                let values = values
+                    .into_iter()
+                    .zip(&selected)
+                    .map(|(value, &index)| datvalue_to_csv_cell(value, enumerations[index]));
+                wtr.write_record(values)?;
+            }
+            wtr.flush()?;
+        }
+        OutputFormat::Json | OutputFormat::Ndjson => {
+            let ndjson = matches!(options.format, OutputFormat::Ndjson);
+            if !ndjson {
+                writer.write_all(b"[")?;
+            }
+            for (i, values) in rows.enumerate() {
+                let object = values
+                    .into_iter()
+                    .zip(&selected)
+                    .map(|(value, &index)| {
+                        let value = datvalue_to_json(value, enumerations[index]);
+                        (names[index].clone(), value)
+                    })
+                    .collect::<serde_json::Map<_, _>>();
+                if ndjson {
+                    serde_json::to_writer(&mut writer, &object)?;
+                    writer.write_all(b"\n")?;
+                } else {
+                    if i > 0 {
+                        writer.write_all(b",")?;
+                    }
+                    serde_json::to_writer(&mut writer, &object)?;
+                }
+            }
+            if !ndjson {
+                writer.write_all(b"]")?;
+            }
+            writer.flush()?;
+        }
     }
-    wtr.flush()?;
     Ok(())
 }
 
+/// Creates table `table_name` with columns of the dat file, replacing existing table, and inserts
+/// all rows in a single transaction. Arrays are stored as JSON text, row references as index of
+/// the referenced row
+fn export_sqlite(
+    dat: &DatFile,
+    columns: &[TableColumn],
+    conn: &rusqlite::Connection,
+    table_name: &str,
+) -> Result<(), anyhow::Error> {
+    let quote = |name: &str| format!("\"{}\"", name.replace('"', "\"\""));
+    let definitions = column_names(columns)
+        .iter()
+        .zip(columns)
+        .map(|(name, column)| {
+            let sql_type = match column.ttype {
+                _ if column.array => "TEXT",
+                ColumnType::String | ColumnType::Array => "TEXT",
+                ColumnType::F32 => "REAL",
+                ColumnType::Bool
+                | ColumnType::I32
+                | ColumnType::I64
+                | ColumnType::U64
+                | ColumnType::Row
+                | ColumnType::ForeignRow
+                | ColumnType::EnumRow => "INTEGER",
+            };
+            format!("{} {}", quote(name), sql_type)
+        })
+        .collect::<Vec<_>>();
+    let placeholders = vec!["?"; columns.len()].join(", ");
+
+    let transaction = conn.unchecked_transaction()?;
+    transaction.execute(&format!("DROP TABLE IF EXISTS {}", quote(table_name)), ())?;
+    transaction.execute(
+        &format!(
+            "CREATE TABLE {} ({})",
+            quote(table_name),
+            definitions.join(", ")
+        ),
+        (),
+    )?;
+    {
+        let mut insert = transaction.prepare(&format!(
+            "INSERT INTO {} VALUES ({})",
+            quote(table_name),
+            placeholders
+        ))?;
+        for values in dat.iter_rows_vec(columns) {
+            let values = values
+                .into_iter()
+                .map(datvalue_to_sql)
+                .collect::<Result<Vec<_>, _>>()?;
+            insert.execute(rusqlite::params_from_iter(values))?;
+        }
+    }
+    transaction.commit()?;
+    Ok(())
+}
+
+fn datvalue_to_sql(value: DatValue) -> Result<rusqlite::types::Value, anyhow::Error> {
+    use rusqlite::types::Value;
+    Ok(match value {
+        DatValue::Bool(b) => Value::Integer(b as i64),
+        DatValue::String(s) => Value::Text(s),
+        DatValue::I32(i) => Value::Integer(i as i64),
+        DatValue::I64(i) => Value::Integer(i),
+        // SQLite integers are signed, larger values are stored as text
+        DatValue::U64(u) => match i64::try_from(u) {
+            Ok(i) => Value::Integer(i),
+            Err(_) => Value::Text(u.to_string()),
+        },
+        DatValue::F32(f) => Value::Real(f as f64),
+        DatValue::Row(r) | DatValue::ForeignRow { rid: r, .. } => {
+            r.map_or(Value::Null, |r| Value::Integer(r as i64))
+        }
+        DatValue::EnumRow(r) => Value::Integer(r as i64),
+        DatValue::Array(a) => Value::Text(serde_json::to_string(&a)?),
+        DatValue::UnknownArray(_, _) => Value::Null,
+    })
+}
+
 fn save_txt_file(
     bytes: Vec<u8>,
     _path: impl AsRef<Path>,
     output: impl AsRef<Path>,
 ) -> Result<(), anyhow::Error> {
-    let vecu16: Vec<u16> = bytes
-        .chunks_exact(2)
-        .map(|a| u16::from_ne_bytes([a[0], a[1]]))
-        .collect();
-    let text = String::from_utf16_lossy(&vecu16);
+    let text = decode_utf16(&bytes);
     std::fs::write(output, text)?;
     Ok(())
 }
@@ -130,9 +548,12 @@ fn save_it_file(
     poefs: &mut PoeFS,
     path: impl AsRef<Path>,
     output: impl AsRef<Path>,
+    compression: Option<Compression>,
 ) -> Result<(), anyhow::Error> {
     let it = poefs.read_it_recursive(path.as_ref().to_str().unwrap())?;
-    std::fs::write(output, serde_json::to_string(&it)?)?;
+    let mut writer = create_output(output.as_ref(), compression)?;
+    serde_json::to_writer(&mut writer, &it)?;
+    writer.flush()?;
     Ok(())
 }
 
@@ -141,51 +562,548 @@ fn get_file(
     path: PathBuf,
     output: PathBuf,
     schema: &SchemaFile,
+    dat_options: &DatOptions,
+    compression: Option<Compression>,
+    raw: bool,
 ) -> Result<(), anyhow::Error> {
-    let extension = path.extension().unwrap().to_str().unwrap();
-    let file_bytes = fs.get_file(path.to_str().unwrap())?.unwrap();
+    let extension = path
+        .extension()
+        .map_or("", |extension| extension.to_str().unwrap());
+    let file_bytes = fs
+        .get_file(path.to_str().unwrap())?
+        .ok_or_else(|| anyhow!("file {} not found", path.display()))?;
+    if raw {
+        std::fs::write(output, file_bytes)?;
+        return Ok(());
+    }
 
     match extension {
-        "dat64" => {
-            save_dat_file(file_bytes, schema, path, output)?;
+        "dat" | "dat64" if dat_options.sqlite.is_some() => {
+            let format = DatFormat::from_extension(extension).unwrap();
+            let dat = DatFile::with_format(file_bytes, format);
+            let table = schema
+                .find_table_for_path(path.to_str().unwrap())
+                .ok_or_else(|| anyhow!("table for {} not found in schema", path.display()))?;
+            let conn = rusqlite::Connection::open(dat_options.sqlite.as_ref().unwrap())?;
+            export_sqlite(&dat, &table.columns, &conn, &table.name)?;
+        }
+        "dat" | "dat64" => {
+            let localized_bytes = match &dat_options.language {
+                Some(language) => {
+                    let localized_path = path
+                        .parent()
+                        .unwrap()
+                        .join(language.to_lowercase())
+                        .join(path.file_name().unwrap());
+                    let localized_path = localized_path.to_str().unwrap();
+                    // tables that are not localized have no file in the language directory
+                    if fs.contains(localized_path) {
+                        fs.get_file(localized_path)?
+                    } else {
+                        None
+                    }
+                }
+                None => None,
+            };
+            save_dat_file(
+                file_bytes,
+                localized_bytes,
+                schema,
+                path,
+                output,
+                dat_options,
+                compression,
+            )?;
         }
         "txt" => {
             save_txt_file(file_bytes, path, output)?;
         }
         "it" => {
-            save_it_file(fs, path, output)?;
+            save_it_file(fs, path, output, compression)?;
         }
         "dds" => {
             save_dds_file(file_bytes, path, output)?;
         }
-        _ => unimplemented!(
-            "Reading files with extension: '{}' not supported yet",
-            extension
-        ),
+        _ => {
+            std::fs::write(output, file_bytes)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Prints values of the row of dat file, values of enum columns are printed with enumerator name
+fn print_row(
+    fs: &mut PoeFS,
+    path: &Path,
+    index: usize,
+    schema: &SchemaFile,
+) -> Result<(), anyhow::Error> {
+    let path = path.to_str().unwrap();
+    let table = schema
+        .find_table_for_path(path)
+        .ok_or_else(|| anyhow!("table for {} not found in schema", path))?;
+    let dat_file = fs.read_dat(path)?;
+    let mut row = dat_file.get(index).ok_or_else(|| {
+        anyhow!(
+            "row {} out of bounds, file has {} rows",
+            index,
+            dat_file.len()
+        )
+    })?;
+    let values = row.read_with_schema(&table.columns);
+    let names = column_names(&table.columns);
+    for ((column, name), value) in table.columns.iter().zip(names).zip(values) {
+        let enumerator = match &value {
+            DatValue::EnumRow(value) => schema
+                .column_enumeration(column)
+                .and_then(|e| e.enumerator_name(*value)),
+            _ => None,
+        };
+        let value = datvalue_to_csv_cell(value, None);
+        match enumerator {
+            Some(enumerator) => println!("{name}: {enumerator} ({value})"),
+            None => println!("{name}: {value}"),
+        }
     }
+    Ok(())
+}
 
+/// Prints index, column name and value of every string cell of dat file containing `query`
+fn search_dat_file(
+    fs: &mut PoeFS,
+    path: &Path,
+    query: &str,
+    schema: &SchemaFile,
+) -> Result<(), anyhow::Error> {
+    let path = path.to_str().unwrap();
+    let table = schema
+        .find_table_for_path(path)
+        .ok_or_else(|| anyhow!("table for {} not found in schema", path))?;
+    let names = column_names(&table.columns);
+    let query = query.to_lowercase();
+    let dat_file = fs.read_dat(path)?;
+    for (index, values) in dat_file.iter_rows_vec(&table.columns).enumerate() {
+        for (name, value) in names.iter().zip(values) {
+            let strings = match value {
+                DatValue::String(s) => vec![s],
+                DatValue::Array(a) => a.iter().filter_map(DatValue::try_as_string).collect(),
+                _ => continue,
+            };
+            for s in strings {
+                if s.to_lowercase().contains(&query) {
+                    println!("{index}: {name}: {s}");
+                }
+            }
+        }
+    }
     Ok(())
 }
 
+const REPL_HELP: &str = "\
+ls [prefix]              print paths starting with prefix
+get <path> [output]      extract file, output defaults to file name of the path
+row <table> <index>      print row of dat file
+search <table> <query>   print rows of dat file containing query
+help                     print this message
+exit                     exit the session
+
+<table> is either path of dat file or name of the table, e.g. 'mods' for 'data/mods.dat64'";
+
+/// Turns table name into path of its dat64 file, paths with extension are returned as they are
+fn repl_table_path(table: &str) -> PathBuf {
+    let path = PathBuf::from(table);
+    if path.extension().is_some() {
+        path
+    } else {
+        PathBuf::from(format!("data/{}.dat64", table.to_lowercase()))
+    }
+}
+
+/// Reads commands from standard input until it is closed or `exit` is entered. File system and
+/// schema are kept between commands so index and dat files are parsed only once
+fn run_repl(fs: &mut PoeFS, args: &Args) -> Result<(), anyhow::Error> {
+    let mut schema = None;
+    let stdin = std::io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("> ");
+        std::io::stdout().flush()?;
+        let Some(line) = lines.next() else {
+            break;
+        };
+        let line = line?;
+        let mut parts = line.trim().splitn(3, char::is_whitespace);
+        let command = parts.next().unwrap_or_default();
+        let first = parts.next();
+        let rest = parts.next().map(str::trim);
+        let result = match (command, first, rest) {
+            ("", _, _) => Ok(()),
+            ("exit" | "quit", _, _) => break,
+            ("help", _, _) => {
+                println!("{REPL_HELP}");
+                Ok(())
+            }
+            ("ls", prefix, None) => {
+                let prefix =
+                    prefix.map_or(String::new(), |p| p.trim_start_matches('/').to_lowercase());
+                for path in fs.sorted_paths() {
+                    if path.starts_with(&prefix) {
+                        println!("{path}");
+                    }
+                }
+                Ok(())
+            }
+            ("get", Some(path), output) => {
+                let path = PathBuf::from(path);
+                let output = match output {
+                    Some(output) => PathBuf::from(output),
+                    None => PathBuf::from(path.file_name().unwrap_or(path.as_os_str())),
+                };
+                repl_schema(&mut schema, args).and_then(|schema| {
+                    get_file(
+                        fs,
+                        path,
+                        output,
+                        schema,
+                        &DatOptions::default(),
+                        None,
+                        false,
+                    )
+                })
+            }
+            ("row", Some(table), Some(index)) => match index.parse() {
+                Ok(index) => repl_schema(&mut schema, args)
+                    .and_then(|schema| print_row(fs, &repl_table_path(table), index, schema)),
+                Err(e) => Err(anyhow!("invalid row index '{}': {}", index, e)),
+            },
+            ("search", Some(table), Some(query)) => repl_schema(&mut schema, args)
+                .and_then(|schema| search_dat_file(fs, &repl_table_path(table), query, schema)),
+            _ => Err(anyhow!(
+                "invalid command '{}', type 'help' to list commands",
+                line.trim()
+            )),
+        };
+        if let Err(e) = result {
+            eprintln!("error: {e}");
+        }
+    }
+    Ok(())
+}
+
+/// Reads schema on first use and returns it for the following commands
+fn repl_schema<'a>(
+    schema: &'a mut Option<SchemaFile>,
+    args: &Args,
+) -> Result<&'a SchemaFile, anyhow::Error> {
+    if schema.is_none() {
+        *schema = Some(read_schema(args)?);
+    }
+    Ok(schema.as_ref().unwrap())
+}
+
+fn read_schema(args: &Args) -> Result<SchemaFile, anyhow::Error> {
+    match &args.schema_path {
+        Some(path) => SchemaFile::read_from_file(path),
+        None => {
+            let cache_path = std::env::temp_dir().join("ggpkcli/schema.min.json");
+            SchemaFile::read_from_online_cached(&cache_path, SCHEMA_CACHE_MAX_AGE)
+        }
+    }
+}
+
 fn main() -> Result<(), anyhow::Error> {
     let args = Args::parse();
-    let schema;
-    let mut fs = if let Some(path) = args.ggpk {
-        schema = SchemaFile::read_from_file(args.schema_path.unwrap())?;
-        PoeFS::new(LocalSource::new(path)?)
+    let mut fs = if let Some(path) = &args.ggpk {
+        PoeFS::try_new(LocalSource::new(path)?)?
     } else if args.online {
-        schema = SchemaFile::read_from_online()?;
-        PoeFS::new(OnlineSource::new(None))
+        let cache_dir = std::env::temp_dir().join("ggpkcli/online");
+        let mut builder = OnlineSource::builder();
+        if let Some(patch) = &args.patch {
+            builder = builder.patch(patch);
+        }
+        if let Some(cdn) = &args.cdn {
+            builder = builder.cdn(cdn);
+        }
+        let source = builder.build()?.with_cache_dir(cache_dir)?;
+        if args.prune_cache {
+            source.prune_cache()?;
+        }
+        PoeFS::try_new(source)?
+    } else if let Some(dir) = &args.dir {
+        PoeFS::try_new(LooseFilesSource::new(dir)?)?
+    } else if args.install {
+        let dir = find_install_dir()?;
+        if dir.join("Content.ggpk").is_file() {
+            PoeFS::try_new(LocalSource::new(dir.join("Content.ggpk"))?)?
+        } else {
+            PoeFS::try_new(LooseFilesSource::new(dir)?)?
+        }
     } else {
         unreachable!()
     };
-    match args.command {
-        Command::Get { file, output } => get_file(&mut fs, file, output, &schema)?,
+    run_command(&mut fs, &args)
+}
+
+/// Runs the command of `args` on the file system, schema is read only by commands that use it
+fn run_command(fs: &mut PoeFS, args: &Args) -> Result<(), anyhow::Error> {
+    match &args.command {
+        Command::Get {
+            file,
+            output,
+            dat_options,
+            compress,
+            raw,
+        } => {
+            let schema = read_schema(args)?;
+            get_file(
+                fs,
+                file.clone(),
+                output.clone().unwrap_or_else(|| {
+                    PathBuf::from(format!("output.{}", dat_options.format.extension()))
+                }),
+                &schema,
+                dat_options,
+                *compress,
+                *raw,
+            )?
+        }
+        Command::Row { file, index } => {
+            let schema = read_schema(args)?;
+            print_row(fs, file, *index, &schema)?
+        }
+        Command::Search { file, query } => {
+            let schema = read_schema(args)?;
+            search_dat_file(fs, file, query, &schema)?
+        }
+        Command::Repl => run_repl(fs, args)?,
         Command::ListPaths => {
-            for path in fs.get_paths() {
+            for path in fs.sorted_paths() {
                 println!("{path}");
             }
         }
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ggpklib::poefs::ExtractedSource;
+
+    /// Creates empty directory in the temporary directory, unique for the test process
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ggpkcli-test-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Builds `.dat64` file from fixed data of all rows and variable data following the marker,
+    /// offsets into variable data start at 8 because of the marker
+    fn dat64(row_count: u32, fixed: &[u8], variable: &[u8]) -> Vec<u8> {
+        let mut bytes = row_count.to_le_bytes().to_vec();
+        bytes.extend(fixed);
+        bytes.extend([0xBB; 8]);
+        bytes.extend(variable);
+        bytes
+    }
+
+    /// Encodes string as it is stored in variable data, UTF-16LE terminated by 4 zero bytes
+    fn dat_string(s: &str) -> Vec<u8> {
+        let mut bytes: Vec<u8> = s.encode_utf16().flat_map(u16::to_le_bytes).collect();
+        bytes.extend([0; 4]);
+        bytes
+    }
+
+    /// Table `A` with a single localized string column `Name`
+    fn localized_schema() -> SchemaFile {
+        SchemaFile::from_tables_json(
+            r#"[{"name": "A", "tags": [], "columns": [
+                {"name": "Name", "array": false, "type": "string", "unique": false, "localized": true}
+            ]}]"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn language_falls_back_for_tables_without_localized_file() {
+        let dir = temp_dir("language");
+        std::fs::create_dir_all(dir.join("game/data")).unwrap();
+        let name_offset = 8u64.to_le_bytes();
+        std::fs::write(
+            dir.join("game/data/a.dat64"),
+            dat64(1, &name_offset, &dat_string("Foo")),
+        )
+        .unwrap();
+        let options = DatOptions {
+            language: Some("French".to_string()),
+            ..Default::default()
+        };
+        let output = dir.join("output.csv");
+        let mut fs = PoeFS::try_new(ExtractedSource::new(dir.join("game")).unwrap()).unwrap();
+        let path = PathBuf::from("data/a.dat64");
+        let schema = localized_schema();
+        get_file(
+            &mut fs,
+            path.clone(),
+            output.clone(),
+            &schema,
+            &options,
+            None,
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&output).unwrap(),
+            "Name [localized]\nFoo\n"
+        );
+
+        std::fs::create_dir_all(dir.join("game/data/french")).unwrap();
+        std::fs::write(
+            dir.join("game/data/french/a.dat64"),
+            dat64(1, &name_offset, &dat_string("Bar")),
+        )
+        .unwrap();
+        let mut fs = PoeFS::try_new(ExtractedSource::new(dir.join("game")).unwrap()).unwrap();
+        get_file(
+            &mut fs,
+            path,
+            output.clone(),
+            &schema,
+            &options,
+            None,
+            false,
+        )
+        .unwrap();
+        assert_eq!(std::fs::read_to_string(&output).unwrap(), "Name\nBar\n");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn commands_without_dat_files_do_not_read_schema() {
+        let dir = temp_dir("no-schema");
+        std::fs::create_dir_all(dir.join("data")).unwrap();
+        std::fs::write(dir.join("data/a.dat64"), [0u8; 4]).unwrap();
+        let mut fs = PoeFS::try_new(ExtractedSource::new(&dir).unwrap()).unwrap();
+        let missing_schema = dir.join("missing.json");
+        let args = |command: &[&str]| {
+            let mut argv = vec!["ggpkcli", "--dir", dir.to_str().unwrap()];
+            argv.extend(["--schema-path", missing_schema.to_str().unwrap()]);
+            argv.extend(command);
+            Args::try_parse_from(argv).unwrap()
+        };
+
+        run_command(&mut fs, &args(&["list-paths"])).unwrap();
+        let error = run_command(&mut fs, &args(&["row", "data/a.dat64", "0"])).unwrap_err();
+        assert!(error.to_string().contains("missing.json"), "{error}");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn json_export_writes_row_objects() {
+        let dir = temp_dir("json");
+        let schema = SchemaFile::from_tables_json(
+            r#"[{"name": "A", "tags": [], "columns": [
+                {"name": "Id", "array": false, "type": "string", "unique": true, "localized": false},
+                {"name": "Levels", "array": true, "type": "i32", "unique": false, "localized": false},
+                {"name": "Parent", "array": false, "type": "row", "unique": false, "localized": false}
+            ]}]"#,
+        )
+        .unwrap();
+        let mut fixed = Vec::new();
+        let mut variable = dat_string("First");
+        let levels_offset = 8 + variable.len() as u64;
+        variable.extend([1i32, 2].iter().flat_map(|i| i.to_le_bytes()));
+        let second_offset = 8 + variable.len() as u64;
+        variable.extend(dat_string("Second"));
+        for (id, levels, parent) in [(8, 2, 0xfefefefefefefefe), (second_offset, 0, 0)] {
+            fixed.extend(u64::to_le_bytes(id));
+            fixed.extend(u64::to_le_bytes(levels));
+            fixed.extend(levels_offset.to_le_bytes());
+            fixed.extend(u64::to_le_bytes(parent));
+        }
+        let output = dir.join("a.json");
+        let options = DatOptions {
+            format: OutputFormat::Json,
+            ..Default::default()
+        };
+        save_dat_file(
+            dat64(2, &fixed, &variable),
+            None,
+            &schema,
+            "data/a.dat64",
+            &output,
+            &options,
+            None,
+        )
+        .unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&output).unwrap()).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!([
+                {"Id": "First", "Levels": [1, 2], "Parent": null},
+                {"Id": "Second", "Levels": [], "Parent": 0},
+            ])
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn sqlite_export_round_trips() {
+        let schema = SchemaFile::from_tables_json(
+            r#"[{"name": "A", "tags": [], "columns": [
+                {"name": "Id", "array": false, "type": "string", "unique": true, "localized": false},
+                {"name": "Levels", "array": true, "type": "i32", "unique": false, "localized": false},
+                {"name": "Parent", "array": false, "type": "row", "unique": false, "localized": false}
+            ]}]"#,
+        )
+        .unwrap();
+        let mut variable = dat_string("First");
+        let levels_offset = 8 + variable.len() as u64;
+        variable.extend([1i32, 2].iter().flat_map(|i| i.to_le_bytes()));
+        let mut fixed = 8u64.to_le_bytes().to_vec();
+        fixed.extend(2u64.to_le_bytes());
+        fixed.extend(levels_offset.to_le_bytes());
+        fixed.extend(0xfefefefefefefefeu64.to_le_bytes());
+        let dat = DatFile::new(dat64(1, &fixed, &variable));
+
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        let table = schema.find_table("A").unwrap();
+        export_sqlite(&dat, &table.columns, &conn, &table.name).unwrap();
+        let row: (String, String, Option<i64>) = conn
+            .query_row("SELECT Id, Levels, Parent FROM A", (), |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })
+            .unwrap();
+        assert_eq!(row, ("First".to_string(), "[1,2]".to_string(), None));
+    }
+
+    #[test]
+    fn sqlite_conflicts_with_options_it_ignores() {
+        let args = |option: &[&str]| {
+            let mut argv = vec!["ggpkcli", "--online", "get", "data/a.dat64"];
+            argv.extend(["--sqlite", "a.db"]);
+            argv.extend(option);
+            Args::try_parse_from(argv)
+        };
+        assert!(args(&[]).is_ok());
+        for option in [
+            &["--columns", "Id"][..],
+            &["--rows", "0..1"],
+            &["--language", "French"],
+            &["--trim"],
+            &["--format", "json"],
+            &["--describe"],
+            &["--compress", "gzip"],
+            &["output.csv"],
+        ] {
+            let error = args(option).unwrap_err();
+            assert_eq!(error.kind(), clap::error::ErrorKind::ArgumentConflict);
+        }
+    }
+}
@@ -0,0 +1,42 @@
+//! Compares decompression time of `Bundle::data` and `Bundle::data_parallel`
+//!
+//! `cargo run --release -p ggpklib --features rayon --example decompress_bundle -- <path to .bundle.bin> [runs]`
+
+use std::{io::Cursor, time::Instant};
+
+use ggpklib::bundle::Bundle;
+
+fn main() -> Result<(), anyhow::Error> {
+    let mut args = std::env::args().skip(1);
+    let path = args
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("path to bundle file is required"))?;
+    let runs: u32 = args.next().map(|r| r.parse()).transpose()?.unwrap_or(10);
+
+    let bytes = std::fs::read(&path)?;
+    let mut c = Cursor::new(bytes.as_slice());
+    let bundle = Bundle::parse(&mut c)?;
+    let payload = &bytes[c.position() as usize..];
+    println!(
+        "{path}: {} blocks, {} bytes uncompressed, {} threads",
+        bundle.head_payload.block_count,
+        bundle.head_payload.uncompressed_size,
+        rayon::current_num_threads()
+    );
+
+    let start = Instant::now();
+    for _ in 0..runs {
+        bundle.data(&mut Cursor::new(payload))?;
+    }
+    let sequential = start.elapsed() / runs;
+    let start = Instant::now();
+    for _ in 0..runs {
+        bundle.data_parallel(&mut Cursor::new(payload))?;
+    }
+    let parallel = start.elapsed() / runs;
+    println!(
+        "data: {sequential:?}, data_parallel: {parallel:?}, speedup: {:.2}x",
+        sequential.as_secs_f64() / parallel.as_secs_f64()
+    );
+    Ok(())
+}
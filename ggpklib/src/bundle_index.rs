@@ -1,10 +1,13 @@
-use std::io::{self};
+use std::{
+    fmt,
+    io::{self},
+};
 
 use byteorder::{LittleEndian, ReadBytesExt};
 
 use crate::bundle::Bundle;
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct BundleIndex {
     pub bundle_count: u32,
     pub bundles: Vec<BundleRecord>,
@@ -17,6 +20,8 @@ pub struct BundleIndex {
 }
 
 impl BundleIndex {
+    /// Parses and validates the index, returns `io::Error` of kind InvalidData containing
+    /// `InvalidIndex` if the index is parsed but inconsistent
     pub fn parse(reader: &mut impl io::Read) -> Result<Self, io::Error> {
         let bundle_count = reader.read_u32::<LittleEndian>()?;
         let mut bundles = Vec::with_capacity(bundle_count as usize);
@@ -39,7 +44,7 @@ impl BundleIndex {
         let path_rep_bundle = Bundle::parse(reader)?;
         let path_rep_data = path_rep_bundle.data(reader)?;
 
-        Ok(Self {
+        let index = Self {
             bundle_count,
             bundles,
             files_count,
@@ -48,10 +53,86 @@ impl BundleIndex {
             path_rep,
             path_rep_bundle,
             path_rep_data,
-        })
+        };
+        index
+            .validate()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(index)
+    }
+
+    /// Checks that every file record points to an existing bundle and every path rep payload
+    /// fits within path rep data
+    pub fn validate(&self) -> Result<(), InvalidIndex> {
+        for (file_index, file) in self.files.iter().enumerate() {
+            if file.bundle_index as usize >= self.bundles.len() {
+                return Err(InvalidIndex::BundleOutOfRange {
+                    file_index,
+                    bundle_index: file.bundle_index,
+                    bundle_count: self.bundles.len(),
+                });
+            }
+        }
+        for (path_rep_index, path_rep) in self.path_rep.iter().enumerate() {
+            let start = path_rep.payload_offset as usize;
+            let end = start + path_rep.payload_size as usize;
+            if end > self.path_rep_data.len() {
+                return Err(InvalidIndex::PayloadOutOfRange {
+                    path_rep_index,
+                    start,
+                    end,
+                    data_length: self.path_rep_data.len(),
+                });
+            }
+        }
+        Ok(())
     }
 }
 
+/// Error returned when the index is parsed successfully but its records are inconsistent, most
+/// likely because the index is corrupted or has an unsupported format
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InvalidIndex {
+    /// File record refers to a bundle that doesn't exist
+    BundleOutOfRange {
+        file_index: usize,
+        bundle_index: u32,
+        bundle_count: usize,
+    },
+    /// Path rep payload range exceeds path rep data
+    PayloadOutOfRange {
+        path_rep_index: usize,
+        start: usize,
+        end: usize,
+        data_length: usize,
+    },
+}
+
+impl fmt::Display for InvalidIndex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvalidIndex::BundleOutOfRange {
+                file_index,
+                bundle_index,
+                bundle_count,
+            } => write!(
+                f,
+                "file record {file_index} refers to bundle {bundle_index}, but index has only {bundle_count} bundles"
+            ),
+            InvalidIndex::PayloadOutOfRange {
+                path_rep_index,
+                start,
+                end,
+                data_length,
+            } => write!(
+                f,
+                "path rep {path_rep_index} payload {start}..{end} is out of bounds of path rep data with length {data_length}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for InvalidIndex {}
+
 #[derive(Debug)]
 pub struct BundleRecord {
     pub name_length: u32,
@@ -1,18 +1,65 @@
-use std::{
-    collections::HashMap,
-    io::{Cursor, Seek, SeekFrom},
-    ops::Range,
-};
+use std::{collections::HashMap, io::Cursor, ops::Range};
 
 use byteorder::{LittleEndian, ReadBytesExt};
 
 use crate::dat_schema::{ColumnType, TableColumn};
 
-type ReadFn = fn(&mut Cursor<&[u8]>, &[u8]) -> DatValue;
+type ReadFn = fn(&mut Cursor<&[u8]>, &[u8], DatFormat) -> DatValue;
+
+/// Format of the dat file, formats differ by size of pointers, offsets and keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DatFormat {
+    /// `.dat` files with 4 byte pointers
+    Dat,
+    /// `.dat64` files with 8 byte pointers
+    #[default]
+    Dat64,
+}
+
+impl DatFormat {
+    /// Detects format from the file extension, returns None for unknown extensions
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_lowercase().as_str() {
+            "dat" => Some(Self::Dat),
+            "dat64" => Some(Self::Dat64),
+            _ => None,
+        }
+    }
+
+    /// Returns the size of pointers in bytes
+    pub fn pointer_size(self) -> usize {
+        match self {
+            Self::Dat => 4,
+            Self::Dat64 => 8,
+        }
+    }
+
+    fn read_pointer(self, reader: &mut Cursor<&[u8]>) -> u64 {
+        match self {
+            Self::Dat => reader.read_u32::<LittleEndian>().unwrap() as u64,
+            Self::Dat64 => reader.read_u64::<LittleEndian>().unwrap(),
+        }
+    }
+
+    /// Reads row key, keys filled with 0xfe are null
+    fn read_key(self, reader: &mut Cursor<&[u8]>) -> Option<usize> {
+        let value = self.read_pointer(reader);
+        let null = match self {
+            Self::Dat => 0xfefefefe,
+            Self::Dat64 => 0xfefefefefefefefe,
+        };
+        if value == null {
+            None
+        } else {
+            Some(value as usize)
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct DatFile {
     data: Vec<u8>,
+    format: DatFormat,
     row_count: u32,
     row_length: usize,
     fixed_data_range: Range<usize>,
@@ -20,19 +67,27 @@ pub struct DatFile {
 }
 
 impl DatFile {
+    /// Creates `.dat64` file from its bytes
     pub fn new(data: Vec<u8>) -> Self {
+        Self::with_format(data, DatFormat::Dat64)
+    }
+
+    /// Creates dat file of given format from its bytes
+    pub fn with_format(data: Vec<u8>, format: DatFormat) -> Self {
         let row_count = u32::from_le_bytes([data[0], data[1], data[2], data[3]]);
         let boundary = data
             .windows(8)
             .position(|wind| wind.iter().all(|b| *b == 0xBB))
             .unwrap();
-        let row_length = ((boundary as u32 - 4) / row_count) as usize;
+        // empty tables have no fixed data, so row length can't be computed from it
+        let row_length = (boundary as u32 - 4).checked_div(row_count).unwrap_or(0) as usize;
 
         let fixed_data_range = 4..boundary;
         let variable_data_range = boundary..data.len();
 
         Self {
             data,
+            format,
             row_count,
             row_length,
             fixed_data_range,
@@ -40,6 +95,11 @@ impl DatFile {
         }
     }
 
+    /// Returns the format of the file
+    pub fn format(&self) -> DatFormat {
+        self.format
+    }
+
     /// Returns the row length in bytes
     pub fn row_length(&self) -> usize {
         self.row_length
@@ -50,6 +110,36 @@ impl DatFile {
         self.row_count
     }
 
+    /// Returns the number of rows
+    pub fn len(&self) -> usize {
+        self.row_count as usize
+    }
+
+    /// Returns true if file has no rows
+    pub fn is_empty(&self) -> bool {
+        self.row_count == 0
+    }
+
+    /// Returns all bytes of the file
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Returns offset of the `0xBB` marker separating fixed and variable length data
+    pub fn boundary(&self) -> usize {
+        self.variable_data_range.start
+    }
+
+    /// Returns range of fixed length data in the file bytes, see `fixed_data`
+    pub fn fixed_data_range(&self) -> Range<usize> {
+        self.fixed_data_range.clone()
+    }
+
+    /// Returns range of variable length data in the file bytes, see `variable_data`
+    pub fn variable_data_range(&self) -> Range<usize> {
+        self.variable_data_range.clone()
+    }
+
     /// Returns byte slice of data where fixed length data is located, length of the slice is equal
     /// to the row length in bytes * the number of rows
     pub fn fixed_data(&self) -> &[u8] {
@@ -61,13 +151,42 @@ impl DatFile {
         &self.data[self.variable_data_range.clone()]
     }
 
-    /// Returns the nth row
+    /// Returns the nth row, see `try_nth_row` for a non panicking variant
+    ///
+    /// # Panics:
+    /// If `n` is greater than or equal to the number of rows
     pub fn nth_row(&self, n: usize) -> DatRow {
         let start = n * self.row_length;
         let end = start + self.row_length;
         DatRow {
             fixed_cursor: Cursor::new(&self.fixed_data()[start..end]),
             variable_data: self.variable_data(),
+            format: self.format,
+        }
+    }
+
+    /// Returns the nth row, or None if it is out of bounds
+    pub fn try_nth_row(&self, n: usize) -> Option<DatRow<'_>> {
+        (n < self.len()).then(|| self.nth_row(n))
+    }
+
+    /// Returns the nth row, or None if it is out of bounds. Same as `try_nth_row`
+    pub fn get(&self, n: usize) -> Option<DatRow<'_>> {
+        self.try_nth_row(n)
+    }
+
+    /// Reads elements of DatValue::UnknownArray as values of `element` type and returns them as
+    /// DatValue::Array, other variants are returned unchanged
+    pub fn resolve_unknown_array(&self, value: &DatValue, element: ColumnType) -> DatValue {
+        match value {
+            DatValue::UnknownArray(offset, length) => DatValue::Array(read_array_elements(
+                self.variable_data(),
+                *offset,
+                *length,
+                element,
+                self.format,
+            )),
+            _ => value.clone(),
         }
     }
 
@@ -85,6 +204,33 @@ impl DatFile {
             .map(|mut row| row.read_with_schema(columns))
     }
 
+    /// Same as `iter_rows_vec` but for dat files of the given game version, see
+    /// `DatRow::read_with_schema_versioned`
+    pub fn iter_rows_vec_versioned<'a>(
+        &'a self,
+        columns: &'a [TableColumn],
+        version: &'a str,
+    ) -> impl Iterator<Item = Vec<DatValue>> + 'a {
+        self.iter_rows()
+            .map(move |mut row| row.read_with_schema_versioned(columns, version))
+    }
+
+    /// Returns indices of all rows where value of the column equals to `needle`
+    pub fn find_all_rows(
+        &self,
+        columns: &[TableColumn],
+        column_name: &str,
+        needle: &DatValue,
+    ) -> Vec<usize> {
+        self.iter_rows()
+            .enumerate()
+            .filter_map(|(index, mut row)| {
+                let value = row.read_column(columns, column_name)?;
+                (value == *needle).then_some(index)
+            })
+            .collect()
+    }
+
     /// Returns an iterator over the rows, reading rows with schema to HashMap
     pub fn iter_rows_map<'a>(
         &'a self,
@@ -94,6 +240,25 @@ impl DatFile {
     }
 }
 
+/// Returns fixed length data of the nth row, use `nth_row` or `get` to parse it
+///
+/// # Panics:
+/// If the row is out of bounds
+impl std::ops::Index<usize> for DatFile {
+    type Output = [u8];
+
+    fn index(&self, n: usize) -> &[u8] {
+        assert!(
+            n < self.len(),
+            "row index {n} out of bounds, file has {} rows",
+            self.len()
+        );
+        let start = n * self.row_length;
+        &self.fixed_data()[start..start + self.row_length]
+    }
+}
+
+/// Reads null terminated UTF-16LE string starting at `offset` of variable data
 pub fn read_variable_string(data: &[u8], offset: usize) -> String {
     let data = &data[offset..];
     let length = data
@@ -103,7 +268,7 @@ pub fn read_variable_string(data: &[u8], offset: usize) -> String {
         .unwrap();
     let vecu16: Vec<u16> = data[..length]
         .chunks_exact(2)
-        .map(|a| u16::from_ne_bytes([a[0], a[1]]))
+        .map(|a| u16::from_le_bytes([a[0], a[1]]))
         .collect();
     String::from_utf16_lossy(&vecu16)
 }
@@ -112,6 +277,7 @@ pub fn read_variable_string(data: &[u8], offset: usize) -> String {
 pub struct DatRow<'a> {
     fixed_cursor: Cursor<&'a [u8]>,
     variable_data: &'a [u8],
+    format: DatFormat,
 }
 
 impl<'a> AsRef<[u8]> for DatRow<'a> {
@@ -135,6 +301,27 @@ impl<'a> DatRow<'a> {
         values
     }
 
+    /// Same as `read_with_schema` but skips columns whose `until` version is lower than
+    /// `version`, since dat files of that version don't have them. Returned values match columns
+    /// of `SchemaTable::columns_for_version`
+    pub fn read_with_schema_versioned(
+        &mut self,
+        columns: &[TableColumn],
+        version: &str,
+    ) -> Vec<DatValue> {
+        columns
+            .iter()
+            .filter(|c| c.exists_in_version(version))
+            .map(|column| {
+                if column.array {
+                    self.read_array(column)
+                } else {
+                    self.read_scalar(column)
+                }
+            })
+            .collect()
+    }
+
     /// Parse a row using provided column definitions and return a HashMap where keys are column names
     pub fn read_to_map(&mut self, columns: &[TableColumn]) -> HashMap<String, DatValue> {
         let mut unknown_column_count = 0;
@@ -155,103 +342,487 @@ impl<'a> DatRow<'a> {
         values
     }
 
+    /// Parses a row into a struct whose fields are named after columns, see `read_to_map`
+    ///
+    /// # Usage:
+    /// ```
+    /// use ggpklib::{dat::DatFile, dat_schema::SchemaFile};
+    ///
+    /// #[derive(serde::Deserialize)]
+    /// #[serde(rename_all = "PascalCase")]
+    /// struct Mod {
+    ///     id: String,
+    ///     level: i32,
+    /// }
+    ///
+    /// let schema = SchemaFile::from_tables_json(
+    ///     r#"[{"name": "Mods", "tags": [], "columns": [
+    ///         {"name": "Id", "array": false, "type": "string", "unique": true, "localized": false},
+    ///         {"name": "Level", "array": false, "type": "i32", "unique": false, "localized": false}
+    ///     ]}]"#,
+    /// )?;
+    /// let columns = &schema.find_table("Mods").unwrap().columns;
+    ///
+    /// // one row with string offset and level, followed by variable data holding the string
+    /// let mut bytes = 1u32.to_le_bytes().to_vec();
+    /// bytes.extend(8u64.to_le_bytes());
+    /// bytes.extend(5i32.to_le_bytes());
+    /// bytes.extend([0xBB; 8]);
+    /// bytes.extend("Foo".encode_utf16().flat_map(u16::to_le_bytes));
+    /// bytes.extend([0; 4]);
+    /// let dat = DatFile::new(bytes);
+    ///
+    /// let m: Mod = dat.nth_row(0).into_typed(columns)?;
+    /// assert_eq!(m.id, "Foo");
+    /// assert_eq!(m.level, 5);
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn into_typed<T: serde::de::DeserializeOwned>(
+        mut self,
+        columns: &[TableColumn],
+    ) -> Result<T, serde_json::Error> {
+        let map = self.read_to_map(columns);
+        serde_json::from_value(serde_json::to_value(map)?)
+    }
+
+    /// Reads value of a single column by its name, skipping preceding columns without parsing
+    /// them. Returns None if there is no column with that name
+    pub fn read_column(&mut self, columns: &[TableColumn], name: &str) -> Option<DatValue> {
+        let index = columns
+            .iter()
+            .position(|c| c.name.as_deref() == Some(name))?;
+        let offset: usize = columns[..index]
+            .iter()
+            .map(|c| column_size(c, self.format))
+            .sum();
+        self.fixed_cursor.set_position(offset as u64);
+        let column = &columns[index];
+        let value = if column.array {
+            self.read_array(column)
+        } else {
+            self.read_scalar(column)
+        };
+        Some(value)
+    }
+
     fn get_fn(column: &TableColumn) -> ReadFn {
-        match column.ttype {
-            ColumnType::Bool => read_bool,
-            ColumnType::String => read_string,
-            ColumnType::I32 => read_i32,
-            ColumnType::F32 => todo!(),
-            ColumnType::Array => read_unknown_array,
-            ColumnType::Row => read_key,
-            ColumnType::ForeignRow => read_foreign_key,
-            ColumnType::EnumRow => read_enum_row,
-        }
+        get_type_fn(column.ttype)
     }
 
     fn read_scalar(&mut self, column: &TableColumn) -> DatValue {
         let f = Self::get_fn(column);
-        f(&mut self.fixed_cursor, self.variable_data)
+        f(&mut self.fixed_cursor, self.variable_data, self.format)
     }
 
     fn read_array(&mut self, column: &TableColumn) -> DatValue {
-        let f = Self::get_fn(column);
-        let array_length = self.fixed_cursor.read_u64::<LittleEndian>().unwrap();
-        let mut arr = Vec::new();
-        let variable_offset = self.fixed_cursor.read_u64::<LittleEndian>().unwrap();
-        let mut variable_reader = Cursor::new(self.variable_data);
-        variable_reader
-            .seek(SeekFrom::Start(variable_offset))
-            .unwrap();
-        for _ in 0..array_length {
-            arr.push(f(&mut variable_reader, self.variable_data))
-        }
+        let array_length = self.format.read_pointer(&mut self.fixed_cursor);
+        let variable_offset = self.format.read_pointer(&mut self.fixed_cursor);
+        let arr = read_array_elements(
+            self.variable_data,
+            variable_offset,
+            array_length,
+            column.ttype,
+            self.format,
+        );
         DatValue::Array(arr)
     }
 }
 
-fn read_string(fixed_reader: &mut Cursor<&[u8]>, variable_data: &[u8]) -> DatValue {
-    let string_offset = fixed_reader.read_u64::<LittleEndian>().unwrap();
+fn get_type_fn(ttype: ColumnType) -> ReadFn {
+    match ttype {
+        ColumnType::Bool => read_bool,
+        ColumnType::String => read_string,
+        ColumnType::I32 => read_i32,
+        ColumnType::I64 => read_i64,
+        ColumnType::U64 => read_u64,
+        ColumnType::F32 => read_f32,
+        ColumnType::Array => read_unknown_array,
+        ColumnType::Row => read_key,
+        ColumnType::ForeignRow => read_foreign_key,
+        ColumnType::EnumRow => read_enum_row,
+    }
+}
+
+/// Reads `length` elements of type `ttype` starting at `offset` in variable data
+fn read_array_elements(
+    variable_data: &[u8],
+    offset: u64,
+    length: u64,
+    ttype: ColumnType,
+    format: DatFormat,
+) -> Vec<DatValue> {
+    let f = get_type_fn(ttype);
+    // elements are laid out with the same size as scalar column of the same type, position
+    // of each element is computed from its index so element readers can't misalign them
+    let stride = element_size(ttype, format) as u64;
+    let mut variable_reader = Cursor::new(variable_data);
+    let mut arr = Vec::new();
+    for index in 0..length {
+        variable_reader.set_position(offset + index * stride);
+        arr.push(f(&mut variable_reader, variable_data, format))
+    }
+    arr
+}
+
+fn read_string(
+    fixed_reader: &mut Cursor<&[u8]>,
+    variable_data: &[u8],
+    format: DatFormat,
+) -> DatValue {
+    let string_offset = format.read_pointer(fixed_reader);
     let string = read_variable_string(variable_data, string_offset as usize);
     DatValue::String(string)
 }
 
-fn read_i32(fixed_reader: &mut Cursor<&[u8]>, _: &[u8]) -> DatValue {
+fn read_i32(fixed_reader: &mut Cursor<&[u8]>, _: &[u8], _: DatFormat) -> DatValue {
     let value = fixed_reader.read_i32::<LittleEndian>().unwrap();
     DatValue::I32(value)
 }
 
-fn read_foreign_key(fixed_reader: &mut Cursor<&[u8]>, _: &[u8]) -> DatValue {
-    let rid = wrap_usize(fixed_reader.read_u64::<LittleEndian>().unwrap() as usize);
-    let unknown = wrap_usize(fixed_reader.read_u64::<LittleEndian>().unwrap() as usize);
+fn read_i64(fixed_reader: &mut Cursor<&[u8]>, _: &[u8], _: DatFormat) -> DatValue {
+    let value = fixed_reader.read_i64::<LittleEndian>().unwrap();
+    DatValue::I64(value)
+}
+
+fn read_u64(fixed_reader: &mut Cursor<&[u8]>, _: &[u8], _: DatFormat) -> DatValue {
+    let value = fixed_reader.read_u64::<LittleEndian>().unwrap();
+    DatValue::U64(value)
+}
+
+fn read_f32(fixed_reader: &mut Cursor<&[u8]>, _: &[u8], _: DatFormat) -> DatValue {
+    let value = fixed_reader.read_f32::<LittleEndian>().unwrap();
+    DatValue::F32(value)
+}
+
+fn read_foreign_key(fixed_reader: &mut Cursor<&[u8]>, _: &[u8], format: DatFormat) -> DatValue {
+    let rid = format.read_key(fixed_reader);
+    let unknown = format.read_key(fixed_reader);
     DatValue::ForeignRow { rid, unknown }
 }
 
-fn read_enum_row(fixed_reader: &mut Cursor<&[u8]>, _: &[u8]) -> DatValue {
+fn read_enum_row(fixed_reader: &mut Cursor<&[u8]>, _: &[u8], _: DatFormat) -> DatValue {
     let row = fixed_reader.read_i32::<LittleEndian>().unwrap();
     DatValue::EnumRow(row as usize)
 }
 
-fn read_bool(fixed_reader: &mut Cursor<&[u8]>, _: &[u8]) -> DatValue {
+fn read_bool(fixed_reader: &mut Cursor<&[u8]>, _: &[u8], _: DatFormat) -> DatValue {
     let value = fixed_reader.read_u8().unwrap();
     DatValue::Bool(value > 0)
 }
 
-fn read_key(fixed_reader: &mut Cursor<&[u8]>, _: &[u8]) -> DatValue {
-    let row = wrap_usize(fixed_reader.read_u64::<LittleEndian>().unwrap() as usize);
+fn read_key(fixed_reader: &mut Cursor<&[u8]>, _: &[u8], format: DatFormat) -> DatValue {
+    let row = format.read_key(fixed_reader);
     DatValue::Row(row)
 }
 
-fn read_unknown_array(fixed_reader: &mut Cursor<&[u8]>, _: &[u8]) -> DatValue {
-    let array_length = fixed_reader.read_u64::<LittleEndian>().unwrap();
-    let variable_offset = fixed_reader.read_u64::<LittleEndian>().unwrap();
+fn read_unknown_array(fixed_reader: &mut Cursor<&[u8]>, _: &[u8], format: DatFormat) -> DatValue {
+    let array_length = format.read_pointer(fixed_reader);
+    let variable_offset = format.read_pointer(fixed_reader);
     DatValue::UnknownArray(variable_offset, array_length)
 }
 
-const fn wrap_usize(value: usize) -> Option<usize> {
-    if value == 0xfefefefefefefefe {
-        None
-    } else {
-        Some(value)
+/// Returns the size of the column in the fixed data section in bytes
+pub(crate) fn column_size(column: &TableColumn, format: DatFormat) -> usize {
+    if column.array {
+        return format.pointer_size() * 2;
+    }
+    element_size(column.ttype, format)
+}
+
+/// Returns the size of a single value of the type in bytes, both in fixed data section and as
+/// an array element in variable data section
+fn element_size(ttype: ColumnType, format: DatFormat) -> usize {
+    let pointer = format.pointer_size();
+    match ttype {
+        ColumnType::Bool => 1,
+        ColumnType::I32 | ColumnType::F32 | ColumnType::EnumRow => 4,
+        ColumnType::I64 | ColumnType::U64 => 8,
+        ColumnType::String | ColumnType::Row => pointer,
+        ColumnType::Array | ColumnType::ForeignRow => pointer * 2,
     }
 }
 
+/// Differences between two versions of the same table
+#[derive(Debug, Clone, Default)]
+pub struct DatDiff {
+    /// Indices of rows in the new file which are not present in the old file
+    pub added: Vec<usize>,
+    /// Indices of rows in the old file which are not present in the new file
+    pub removed: Vec<usize>,
+    /// Cells that have different values in rows present in both files
+    pub changed: Vec<CellChange>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CellChange {
+    pub old_row: usize,
+    pub new_row: usize,
+    pub column: String,
+    pub old: DatValue,
+    pub new: DatValue,
+}
+
+/// Compares two versions of the same table, rows are matched using the first named `unique`
+/// column, or by row index if table has no such column
+pub fn diff(old: &DatFile, new: &DatFile, columns: &[TableColumn]) -> DatDiff {
+    let key_column = columns
+        .iter()
+        .find(|c| c.unique && c.name.is_some())
+        .and_then(|c| c.name.as_deref());
+    diff_by(old, new, columns, key_column)
+}
+
+/// Compares two versions of the same table, rows are matched using values of `key_column`, or
+/// by row index if it is None or there is no column with that name. If multiple rows have the
+/// same key only the last one is compared
+pub fn diff_by(
+    old: &DatFile,
+    new: &DatFile,
+    columns: &[TableColumn],
+    key_column: Option<&str>,
+) -> DatDiff {
+    let names = column_names(columns);
+    let key_index = key_column.and_then(|key| names.iter().position(|n| n == key));
+    // DatValue can't be hashed, so rows are keyed by debug representation of the key value
+    let row_key = |index: usize, row: &[DatValue]| match key_index {
+        Some(key_index) => format!("{:?}", row[key_index]),
+        None => index.to_string(),
+    };
+
+    let old_rows: Vec<Vec<DatValue>> = old.iter_rows_vec(columns).collect();
+    let new_rows: Vec<Vec<DatValue>> = new.iter_rows_vec(columns).collect();
+    let old_keys: HashMap<String, usize> = old_rows
+        .iter()
+        .enumerate()
+        .map(|(i, row)| (row_key(i, row), i))
+        .collect();
+    let new_keys: HashMap<String, usize> = new_rows
+        .iter()
+        .enumerate()
+        .map(|(i, row)| (row_key(i, row), i))
+        .collect();
+
+    let mut diff = DatDiff::default();
+    for (new_index, new_row) in new_rows.iter().enumerate() {
+        let Some(&old_index) = old_keys.get(&row_key(new_index, new_row)) else {
+            diff.added.push(new_index);
+            continue;
+        };
+        let old_row = &old_rows[old_index];
+        for ((name, old_value), new_value) in names.iter().zip(old_row).zip(new_row) {
+            if old_value != new_value {
+                diff.changed.push(CellChange {
+                    old_row: old_index,
+                    new_row: new_index,
+                    column: name.clone(),
+                    old: old_value.clone(),
+                    new: new_value.clone(),
+                });
+            }
+        }
+    }
+    for (old_index, old_row) in old_rows.iter().enumerate() {
+        if !new_keys.contains_key(&row_key(old_index, old_row)) {
+            diff.removed.push(old_index);
+        }
+    }
+    diff
+}
+
+/// Returns names of the columns, unnamed columns are named `Unknown{n}`
+pub fn column_names(columns: &[TableColumn]) -> Vec<String> {
+    let mut unknown_column_count = 0;
+    columns
+        .iter()
+        .map(|column| {
+            column.name.clone().unwrap_or_else(|| {
+                let s = format!("Unknown{unknown_column_count}");
+                unknown_column_count += 1;
+                s
+            })
+        })
+        .collect()
+}
+
 #[derive(Debug, Clone)]
 pub enum DatValue {
     Bool(bool),
     String(String),
     I32(i32),
+    I64(i64),
+    U64(u64),
     F32(f32),
     UnknownArray(u64, u64),
     Array(Vec<DatValue>),
     Row(Option<usize>),
     ForeignRow {
+        /// Index of the row in the referenced table
         rid: Option<usize>,
+        /// Second half of the foreign key, used as a secondary key by some reference types and
+        /// null otherwise
         unknown: Option<usize>,
     },
     EnumRow(usize),
 }
 
+/// Values are equal if they are the same variant with equal content, except numbers: I32, I64,
+/// U64 and F32 are equal if they represent the same number, e.g. `I32(3) == F32(3.0)` and
+/// `I64(-1) != U64(u64::MAX)`. Arrays are equal if they have the same length and their elements
+/// are equal pairwise
+impl PartialEq for DatValue {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::Bool(a), Self::Bool(b)) => a == b,
+            (Self::String(a), Self::String(b)) => a == b,
+            (Self::I32(a), Self::I32(b)) => a == b,
+            (Self::I64(a), Self::I64(b)) => a == b,
+            (Self::U64(a), Self::U64(b)) => a == b,
+            (Self::F32(a), Self::F32(b)) => a == b,
+            (Self::UnknownArray(a_count, a_offset), Self::UnknownArray(b_count, b_offset)) => {
+                a_count == b_count && a_offset == b_offset
+            }
+            (Self::Array(a), Self::Array(b)) => a == b,
+            (Self::Row(a), Self::Row(b)) => a == b,
+            (
+                Self::ForeignRow {
+                    rid: a_rid,
+                    unknown: a_unknown,
+                },
+                Self::ForeignRow {
+                    rid: b_rid,
+                    unknown: b_unknown,
+                },
+            ) => a_rid == b_rid && a_unknown == b_unknown,
+            (Self::EnumRow(a), Self::EnumRow(b)) => a == b,
+            (a, b) => match (a.number(), b.number()) {
+                (Some(a), Some(b)) => a == b,
+                _ => false,
+            },
+        }
+    }
+}
+
+/// Values of the same variant are ordered by their content, numbers are ordered across I32, I64,
+/// U64 and F32, arrays are ordered lexicographically and null row references are ordered before other
+/// references. Values of different variants and arrays of unknown type are not comparable
+impl PartialOrd for DatValue {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        match (self, other) {
+            (Self::Bool(a), Self::Bool(b)) => a.partial_cmp(b),
+            (Self::String(a), Self::String(b)) => a.partial_cmp(b),
+            (Self::I32(a), Self::I32(b)) => a.partial_cmp(b),
+            (Self::I64(a), Self::I64(b)) => a.partial_cmp(b),
+            (Self::U64(a), Self::U64(b)) => a.partial_cmp(b),
+            (Self::F32(a), Self::F32(b)) => a.partial_cmp(b),
+            (Self::Array(a), Self::Array(b)) => a.partial_cmp(b),
+            (Self::Row(a), Self::Row(b)) => a.partial_cmp(b),
+            (
+                Self::ForeignRow {
+                    rid: a_rid,
+                    unknown: a_unknown,
+                },
+                Self::ForeignRow {
+                    rid: b_rid,
+                    unknown: b_unknown,
+                },
+            ) => (a_rid, a_unknown).partial_cmp(&(b_rid, b_unknown)),
+            (Self::EnumRow(a), Self::EnumRow(b)) => a.partial_cmp(b),
+            (a, b) => a.number()?.partial_cmp(&b.number()?),
+        }
+    }
+}
+
+/// Numeric value of I32, I64, U64 or F32 used to compare numbers of different variants,
+/// integers are compared exactly and as f64 if any of the values is a float
+#[derive(Debug, Clone, Copy)]
+enum Number {
+    Integer(i128),
+    Float(f64),
+}
+
+impl Number {
+    fn as_f64(self) -> f64 {
+        match self {
+            Self::Integer(i) => i as f64,
+            Self::Float(f) => f,
+        }
+    }
+}
+
+impl PartialEq for Number {
+    fn eq(&self, other: &Self) -> bool {
+        self.partial_cmp(other) == Some(std::cmp::Ordering::Equal)
+    }
+}
+
+impl PartialOrd for Number {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        match (self, other) {
+            (Self::Integer(a), Self::Integer(b)) => a.partial_cmp(b),
+            (a, b) => a.as_f64().partial_cmp(&b.as_f64()),
+        }
+    }
+}
+
+/// Serializes value as plain data without variant names, foreign rows are serialized as index
+/// of the referenced row and arrays of unknown type as null
+impl serde::Serialize for DatValue {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match self {
+            DatValue::Bool(b) => serializer.serialize_bool(*b),
+            DatValue::String(s) => serializer.serialize_str(s),
+            DatValue::I32(i) => serializer.serialize_i32(*i),
+            DatValue::I64(i) => serializer.serialize_i64(*i),
+            DatValue::U64(u) => serializer.serialize_u64(*u),
+            DatValue::F32(f) => serializer.serialize_f32(*f),
+            DatValue::UnknownArray(_, _) => serializer.serialize_none(),
+            DatValue::Array(a) => a.serialize(serializer),
+            DatValue::Row(r) => r.serialize(serializer),
+            DatValue::ForeignRow { rid, .. } => rid.serialize(serializer),
+            DatValue::EnumRow(r) => serializer.serialize_u64(*r as u64),
+        }
+    }
+}
+
 impl DatValue {
+    /// Returns numeric value of number variants, None for other variants
+    fn number(&self) -> Option<Number> {
+        match self {
+            Self::I32(i) => Some(Number::Integer(*i as i128)),
+            Self::I64(i) => Some(Number::Integer(*i as i128)),
+            Self::U64(u) => Some(Number::Integer(*u as i128)),
+            Self::F32(f) => Some(Number::Float(*f as f64)),
+            _ => None,
+        }
+    }
+
+    /// Trims whitespace around strings, including strings in arrays, other variants are returned
+    /// unchanged. If `strip_control` is true control characters other than line breaks and tabs
+    /// are removed before trimming
+    pub fn normalize_strings(self, strip_control: bool) -> Self {
+        match self {
+            Self::String(s) => {
+                let s = if strip_control {
+                    s.chars()
+                        .filter(|c| !c.is_control() || matches!(c, '\n' | '\r' | '\t'))
+                        .collect()
+                } else {
+                    s
+                };
+                Self::String(s.trim().to_string())
+            }
+            Self::Array(a) => Self::Array(
+                a.into_iter()
+                    .map(|v| v.normalize_strings(strip_control))
+                    .collect(),
+            ),
+            value => value,
+        }
+    }
+
     /// Gets the value as a bool
     ///
     /// # Panics:
@@ -285,6 +856,28 @@ impl DatValue {
         }
     }
 
+    /// Gets the value as an i64
+    ///
+    /// # Panics:
+    /// If the DatValue is not a DatValue::I64 variant
+    pub fn as_i64(&self) -> i64 {
+        match self {
+            Self::I64(i) => *i,
+            _ => panic!("Expected DatValue::I64 variant, got {:?}", self),
+        }
+    }
+
+    /// Gets the value as an u64
+    ///
+    /// # Panics:
+    /// If the DatValue is not a DatValue::U64 variant
+    pub fn as_u64(&self) -> u64 {
+        match self {
+            Self::U64(u) => *u,
+            _ => panic!("Expected DatValue::U64 variant, got {:?}", self),
+        }
+    }
+
     /// Gets the value as a enum row index
     ///
     /// # Panics:
@@ -307,6 +900,15 @@ impl DatValue {
         }
     }
 
+    /// Gets both components of the foreign key, the row index and the secondary key, returns
+    /// None if the DatValue is not a DatValue::ForeignRow variant
+    pub fn as_foreign_row(&self) -> Option<(Option<usize>, Option<usize>)> {
+        match self {
+            Self::ForeignRow { rid, unknown } => Some((*rid, *unknown)),
+            _ => None,
+        }
+    }
+
     /// Gets the value as an row index
     ///
     /// # Panics:
@@ -333,8 +935,11 @@ impl DatValue {
     ///
     /// # Usage:
     /// ```
-    /// let i32_array = datvalue.as_array_with(DatValue::as_i32);
+    /// use ggpklib::dat::DatValue;
     ///
+    /// let datvalue = DatValue::Array(vec![DatValue::I32(1), DatValue::I32(2)]);
+    /// let i32_array = datvalue.as_array_with(DatValue::as_i32);
+    /// assert_eq!(i32_array, vec![1, 2]);
     /// ```
     ///
     /// # Panics:
@@ -346,4 +951,306 @@ impl DatValue {
             _ => panic!("Expected DatValue::Array variant, got {:?}", self),
         }
     }
+
+    /// Gets the value as a bool, returns None if the DatValue is not a DatValue::Bool variant
+    pub fn try_as_bool(&self) -> Option<bool> {
+        match self {
+            Self::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    /// Gets the value as a string, returns None if the DatValue is not a DatValue::String variant
+    pub fn try_as_string(&self) -> Option<String> {
+        match self {
+            Self::String(s) => Some(s.clone()),
+            _ => None,
+        }
+    }
+
+    /// Gets the value as an i32, returns None if the DatValue is not a DatValue::I32 variant
+    pub fn try_as_i32(&self) -> Option<i32> {
+        match self {
+            Self::I32(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    /// Gets the value as an i64, returns None if the DatValue is not a DatValue::I64 variant
+    pub fn try_as_i64(&self) -> Option<i64> {
+        match self {
+            Self::I64(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    /// Gets the value as an u64, returns None if the DatValue is not a DatValue::U64 variant
+    pub fn try_as_u64(&self) -> Option<u64> {
+        match self {
+            Self::U64(u) => Some(*u),
+            _ => None,
+        }
+    }
+
+    /// Gets the value as an f32, returns None if the DatValue is not a DatValue::F32 variant
+    pub fn try_as_f32(&self) -> Option<f32> {
+        match self {
+            Self::F32(f) => Some(*f),
+            _ => None,
+        }
+    }
+
+    /// Gets the value as a enum row index, returns None if the DatValue is not a
+    /// DatValue::EnumRow variant
+    pub fn try_as_enum_row_index(&self) -> Option<usize> {
+        match self {
+            Self::EnumRow(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    /// Gets the value as a foreign row index, returns None if the DatValue is not a
+    /// DatValue::ForeignRow variant
+    pub fn try_as_foreign_row_index(&self) -> Option<Option<usize>> {
+        match self {
+            Self::ForeignRow { rid, .. } => Some(*rid),
+            _ => None,
+        }
+    }
+
+    /// Gets the value as an row index, returns None if the DatValue is not a DatValue::Row
+    /// variant
+    pub fn try_as_row_index(&self) -> Option<Option<usize>> {
+        match self {
+            Self::Row(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    /// Gets the value as an array, returns None if the DatValue is not a DatValue::Array variant
+    pub fn try_as_array(&self) -> Option<Vec<DatValue>> {
+        match self {
+            Self::Array(a) => Some(a.clone()),
+            _ => None,
+        }
+    }
+
+    /// Gets the value as an array of specific type
+    ///
+    /// # Usage:
+    /// ```
+    /// use ggpklib::dat::DatValue;
+    ///
+    /// let datvalue = DatValue::Array(vec![DatValue::I32(1), DatValue::I32(2)]);
+    /// let i32_array = datvalue.try_as_array_with(DatValue::try_as_i32);
+    /// assert_eq!(i32_array, Some(vec![1, 2]));
+    ///
+    /// let mixed = DatValue::Array(vec![DatValue::I32(1), DatValue::Bool(true)]);
+    /// assert_eq!(mixed.try_as_array_with(DatValue::try_as_i32), None);
+    /// ```
+    ///
+    /// Returns None if the DatValue is not a DatValue::Array variant or if any element returns
+    /// None when casting using passed function
+    pub fn try_as_array_with<T>(&self, f: impl Fn(&Self) -> Option<T>) -> Option<Vec<T>> {
+        match self {
+            Self::Array(a) => a.iter().map(f).collect(),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds `.dat64` file from fixed data of all rows and variable data following the marker,
+    /// offsets into variable data start at 8 because of the marker
+    fn dat64(row_count: u32, fixed: &[u8], variable: &[u8]) -> DatFile {
+        let mut bytes = row_count.to_le_bytes().to_vec();
+        bytes.extend(fixed);
+        bytes.extend([0xBB; 8]);
+        bytes.extend(variable);
+        DatFile::new(bytes)
+    }
+
+    fn column(name: &str, ttype: ColumnType, array: bool) -> TableColumn {
+        TableColumn {
+            name: Some(name.to_string()),
+            description: None,
+            array,
+            ttype,
+            unique: false,
+            localized: false,
+            until: None,
+            references: None,
+            file: None,
+            files: None,
+        }
+    }
+
+    fn u64s(values: &[u64]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn empty_table_has_no_rows() {
+        let file = dat64(0, &[], &[]);
+        assert_eq!(file.len(), 0);
+        assert!(file.is_empty());
+        assert_eq!(file.row_length(), 0);
+        assert_eq!(file.iter_rows().count(), 0);
+    }
+
+    #[test]
+    fn rows_past_the_end_are_none() {
+        let file = dat64(2, &[1, 0, 0, 0, 2, 0, 0, 0], &[]);
+        let columns = [column("Value", ColumnType::I32, false)];
+        let mut row = file.try_nth_row(1).unwrap();
+        assert_eq!(row.read_with_schema(&columns), [DatValue::I32(2)]);
+        assert!(file.try_nth_row(2).is_none());
+        assert!(file.get(2).is_none());
+        assert!(file.try_nth_row(usize::MAX / 2).is_none());
+    }
+
+    #[test]
+    fn variable_strings_are_decoded_as_utf16le() {
+        let mut data = vec![0xBB; 8];
+        // ascii string ends with a zero byte, so there are 4 zero bytes at odd index before the
+        // terminator
+        data.extend([b'a', 0, 0, 0, 0, 0]);
+        data.extend("Ünïcode 𝄞".encode_utf16().flat_map(u16::to_le_bytes));
+        data.extend([0; 4]);
+        assert_eq!(read_variable_string(&data, 8), "a");
+        assert_eq!(read_variable_string(&data, 14), "Ünïcode 𝄞");
+    }
+
+    #[test]
+    fn f32_columns_are_read() {
+        let mut fixed = 1.5f32.to_le_bytes().to_vec();
+        fixed.extend(2u64.to_le_bytes());
+        fixed.extend(8u64.to_le_bytes());
+        let variable: Vec<u8> = [-0.25f32, 4.0]
+            .iter()
+            .flat_map(|f| f.to_le_bytes())
+            .collect();
+        let file = dat64(1, &fixed, &variable);
+        let columns = [
+            column("Scalar", ColumnType::F32, false),
+            column("Array", ColumnType::F32, true),
+        ];
+        let values = file.nth_row(0).read_with_schema(&columns);
+        assert_eq!(values[0].try_as_f32(), Some(1.5));
+        assert_eq!(
+            values[1],
+            DatValue::Array(vec![DatValue::F32(-0.25), DatValue::F32(4.0)])
+        );
+    }
+
+    #[test]
+    fn i64_and_u64_columns_are_read() {
+        let fixed = [(-2i64).to_le_bytes(), u64::MAX.to_le_bytes()].concat();
+        let file = dat64(1, &fixed, &[]);
+        let columns = [
+            column("Signed", ColumnType::I64, false),
+            column("Unsigned", ColumnType::U64, false),
+        ];
+        let values = file.nth_row(0).read_with_schema(&columns);
+        assert!(matches!(values[0], DatValue::I64(-2)));
+        assert!(matches!(values[1], DatValue::U64(u64::MAX)));
+    }
+
+    #[test]
+    fn numbers_are_compared_across_variants() {
+        use std::cmp::Ordering;
+
+        assert_eq!(DatValue::I32(3), DatValue::F32(3.0));
+        assert_eq!(DatValue::I64(3), DatValue::U64(3));
+        assert_eq!(DatValue::U64(3), DatValue::I32(3));
+        assert_eq!(DatValue::F32(0.5), DatValue::F32(0.5));
+        assert_ne!(DatValue::I64(-1), DatValue::U64(u64::MAX));
+        assert_ne!(DatValue::I32(1), DatValue::Bool(true));
+        assert_ne!(DatValue::F32(f32::NAN), DatValue::I32(0));
+
+        assert_eq!(
+            DatValue::I64(-1).partial_cmp(&DatValue::U64(0)),
+            Some(Ordering::Less)
+        );
+        assert_eq!(
+            DatValue::U64(u64::MAX).partial_cmp(&DatValue::I64(i64::MAX)),
+            Some(Ordering::Greater)
+        );
+        assert_eq!(
+            DatValue::I32(2).partial_cmp(&DatValue::F32(1.5)),
+            Some(Ordering::Greater)
+        );
+        assert_eq!(DatValue::I32(1).partial_cmp(&DatValue::Bool(true)), None);
+    }
+
+    #[test]
+    fn array_elements_are_read_with_element_stride() {
+        const NULL: u64 = 0xfefefefefefefefe;
+        // 3 row keys at offset 8, then 2 foreign keys at offset 32
+        let fixed = u64s(&[3, 8, 2, 32]);
+        let variable = u64s(&[0, NULL, 2, 5, NULL, NULL, 7]);
+        let file = dat64(1, &fixed, &variable);
+        let columns = [
+            column("Rows", ColumnType::Row, true),
+            column("ForeignRows", ColumnType::ForeignRow, true),
+        ];
+        let values = file.nth_row(0).read_with_schema(&columns);
+        assert_eq!(
+            values[0],
+            DatValue::Array(vec![
+                DatValue::Row(Some(0)),
+                DatValue::Row(None),
+                DatValue::Row(Some(2)),
+            ])
+        );
+        assert_eq!(
+            values[1],
+            DatValue::Array(vec![
+                DatValue::ForeignRow {
+                    rid: Some(5),
+                    unknown: None,
+                },
+                DatValue::ForeignRow {
+                    rid: None,
+                    unknown: Some(7),
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn versioned_rows_skip_removed_columns() {
+        let mut removed = column("Removed", ColumnType::I32, false);
+        removed.until = Some("3.20".to_string());
+        let columns = [
+            column("First", ColumnType::I32, false),
+            removed,
+            column("Last", ColumnType::I32, false),
+        ];
+        let i32s =
+            |values: &[i32]| -> Vec<u8> { values.iter().flat_map(|v| v.to_le_bytes()).collect() };
+
+        let old = dat64(1, &i32s(&[1, 2, 3]), &[]);
+        assert_eq!(
+            old.nth_row(0).read_with_schema_versioned(&columns, "3.20"),
+            [DatValue::I32(1), DatValue::I32(2), DatValue::I32(3)]
+        );
+
+        let new = dat64(2, &i32s(&[1, 3, 4, 6]), &[]);
+        assert_eq!(
+            new.nth_row(1).read_with_schema_versioned(&columns, "3.21"),
+            [DatValue::I32(4), DatValue::I32(6)]
+        );
+        let rows: Vec<_> = new.iter_rows_vec_versioned(&columns, "3.21").collect();
+        assert_eq!(
+            rows,
+            [
+                [DatValue::I32(1), DatValue::I32(3)],
+                [DatValue::I32(4), DatValue::I32(6)]
+            ]
+        );
+    }
 }
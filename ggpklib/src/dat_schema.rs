@@ -1,40 +1,186 @@
-use std::path::Path;
+use std::{collections::HashMap, ops::Range, path::Path, time::Duration};
 
+use once_cell::sync::OnceCell;
+
+use crate::dat::{column_names, column_size, DatFormat};
+
+/// Schema file, `version`, `createdAt` and `enumerations` are optional and default to 0 and empty
+/// list when missing
 #[derive(Debug, serde::Deserialize)]
 pub struct SchemaFile {
+    #[serde(default)]
     pub version: u32,
-    #[serde(rename = "createdAt")]
+    #[serde(rename = "createdAt", default)]
     pub created_at: u32,
-    pub tables: Vec<SchemaTable>,
+    /// Private so the index of `find_table` can be reset when tables change, see `tables_mut`
+    tables: Vec<SchemaTable>,
+    #[serde(default)]
     pub enumerations: Vec<SchemaEnumeration>,
+    /// Lowercased table names mapped to indices in `tables`, built on the first `find_table` call
+    #[serde(skip)]
+    table_index: OnceCell<HashMap<String, usize>>,
 }
 
 impl SchemaFile {
-    /// Reads the content of the file and deserializes it
+    /// Reads the content of the file and deserializes it, errors include the path of the file
     pub fn read_from_file(path: impl AsRef<Path>) -> Result<Self, anyhow::Error> {
-        let content = std::fs::read_to_string(path)?;
-        Ok(serde_json::from_str(&content)?)
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("failed to read schema {}: {}", path.display(), e))?;
+        serde_json::from_str(&content)
+            .map_err(|e| anyhow::anyhow!("failed to parse schema {}: {}", path.display(), e))
     }
 
     pub fn read_from_str(content: &str) -> Result<Self, anyhow::Error> {
         Ok(serde_json::from_str(content)?)
     }
 
+    /// Deserializes schema from JSON containing only an array of tables
+    pub fn from_tables_json(content: &str) -> Result<Self, anyhow::Error> {
+        let tables = serde_json::from_str(content)?;
+        Ok(Self {
+            version: 0,
+            created_at: 0,
+            tables,
+            enumerations: Vec::new(),
+            table_index: OnceCell::new(),
+        })
+    }
+
     pub fn read_from_online() -> Result<Self, anyhow::Error> {
+        Self::read_from_str(&Self::download()?)
+    }
+
+    /// Reads schema from `cache_path` if it is not older than `max_age`, otherwise downloads
+    /// schema and stores it to `cache_path`. If download fails stale cache is used if it exists
+    pub fn read_from_online_cached(
+        cache_path: &Path,
+        max_age: Duration,
+    ) -> Result<Self, anyhow::Error> {
+        let age = std::fs::metadata(cache_path)
+            .and_then(|m| m.modified())
+            .ok()
+            .map(|modified| modified.elapsed().unwrap_or_default());
+        if age.is_some_and(|age| age <= max_age) {
+            return Self::read_from_file(cache_path);
+        }
+
+        let text = match Self::download() {
+            Ok(text) => text,
+            Err(_) if age.is_some() => return Self::read_from_file(cache_path),
+            Err(e) => return Err(e),
+        };
+        let schema = Self::read_from_str(&text)?;
+        if let Some(parent) = cache_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(cache_path, text)?;
+        Ok(schema)
+    }
+
+    fn download() -> Result<String, anyhow::Error> {
         let response = reqwest::blocking::get(
             "https://github.com/poe-tool-dev/dat-schema/releases/download/latest/schema.min.json",
         )?;
-        let text = response.text()?;
-        Self::read_from_str(&text)
+        Ok(response.text()?)
     }
 
+    /// Tables of the schema
+    pub fn tables(&self) -> &[SchemaTable] {
+        &self.tables
+    }
+
+    /// Tables of the schema for changing them, the index used by `find_table` is rebuilt on the
+    /// next lookup
+    pub fn tables_mut(&mut self) -> &mut Vec<SchemaTable> {
+        self.table_index.take();
+        &mut self.tables
+    }
+
+    /// Finds table by its name, case insensitive
+    ///
+    /// If multiple tables differ only by case the first one is returned, use `find_table_exact`
+    /// to distinguish them
+    ///
+    /// Tables are looked up in an index built on the first call
     pub fn find_table(&self, table_name: &str) -> Option<&SchemaTable> {
-        self.tables
+        let index = self.table_index.get_or_init(|| {
+            let mut index = HashMap::with_capacity(self.tables.len());
+            for (i, table) in self.tables.iter().enumerate() {
+                index.entry(table.name.to_ascii_lowercase()).or_insert(i);
+            }
+            index
+        });
+        let &i = index.get(&table_name.to_ascii_lowercase())?;
+        Some(&self.tables[i])
+    }
+
+    /// Finds table of the dat file by its path, table name is the file name without extension
+    pub fn find_table_for_path(&self, path: &str) -> Option<&SchemaTable> {
+        let table_name = Path::new(path).file_stem()?.to_str()?;
+        self.find_table(table_name)
+    }
+
+    /// Finds table by its name, case sensitive
+    pub fn find_table_exact(&self, table_name: &str) -> Option<&SchemaTable> {
+        self.tables.iter().find(|t| t.name == table_name)
+    }
+
+    /// Finds enumeration by its name
+    pub fn find_enumeration(&self, name: &str) -> Option<&SchemaEnumeration> {
+        self.enumerations.iter().find(|e| e.name == name)
+    }
+
+    /// Returns name of the enumerator with the given value of the enumeration, see
+    /// `SchemaEnumeration::enumerator_name`
+    pub fn enum_name(&self, enum_name: &str, value: usize) -> Option<&str> {
+        self.find_enumeration(enum_name)?.enumerator_name(value)
+    }
+
+    /// Returns enumeration referenced by the enum column, None for other columns
+    pub fn column_enumeration(&self, column: &TableColumn) -> Option<&SchemaEnumeration> {
+        match (&column.ttype, &column.references) {
+            (ColumnType::EnumRow, Some(Reference::RefUsingRowIndex { table })) => {
+                self.find_enumeration(table)
+            }
+            _ => None,
+        }
+    }
+
+    /// Compares tables in the schema with `present_tables`, for example ones returned by
+    /// `PoeFS::list_tables`. Names are compared case insensitive
+    pub fn coverage(&self, present_tables: &[String]) -> Coverage {
+        let missing_schema = present_tables
             .iter()
-            .find(|t| t.name.to_lowercase() == table_name)
+            .filter(|name| self.find_table(name).is_none())
+            .cloned()
+            .collect();
+        let missing_file = self
+            .tables
+            .iter()
+            .filter(|t| {
+                !present_tables
+                    .iter()
+                    .any(|name| t.name.eq_ignore_ascii_case(name))
+            })
+            .map(|t| t.name.clone())
+            .collect();
+        Coverage {
+            missing_schema,
+            missing_file,
+        }
     }
 }
 
+/// Result of `SchemaFile::coverage`
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Coverage {
+    /// Present tables that have no schema
+    pub missing_schema: Vec<String>,
+    /// Schema tables that are not present
+    pub missing_file: Vec<String>,
+}
+
 #[derive(Debug, serde::Deserialize)]
 pub struct SchemaTable {
     pub name: String,
@@ -42,7 +188,28 @@ pub struct SchemaTable {
     pub tags: Vec<String>,
 }
 
-#[derive(Debug, serde::Deserialize)]
+impl SchemaTable {
+    /// Returns columns that exist in the given game version, columns whose `until` version is
+    /// lower than `version` are dropped
+    pub fn columns_for_version(&self, version: &str) -> Vec<TableColumn> {
+        self.columns
+            .iter()
+            .filter(|c| c.exists_in_version(version))
+            .cloned()
+            .collect()
+    }
+
+    /// Finds column by its name, case sensitive, returning it with its index in `columns`.
+    /// Unnamed columns are never found
+    pub fn find_column(&self, name: &str) -> Option<(usize, &TableColumn)> {
+        self.columns
+            .iter()
+            .enumerate()
+            .find(|(_, c)| c.name.as_deref() == Some(name))
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
 pub struct TableColumn {
     pub name: Option<String>,
     pub description: Option<String>,
@@ -57,12 +224,61 @@ pub struct TableColumn {
     pub files: Option<Vec<String>>,
 }
 
-#[derive(Debug, serde::Deserialize)]
+impl TableColumn {
+    /// Returns true if column is present in the given game version
+    pub fn exists_in_version(&self, version: &str) -> bool {
+        match &self.until {
+            Some(until) => parse_version(until) >= parse_version(version),
+            None => true,
+        }
+    }
+}
+
+/// Returns names of the columns with their byte ranges within a row of `.dat64` file, use
+/// `column_offsets_with_format` for `.dat` files
+pub fn column_offsets(columns: &[TableColumn]) -> Vec<(String, Range<usize>)> {
+    column_offsets_with_format(columns, DatFormat::Dat64)
+}
+
+/// Returns names of the columns with their byte ranges within a row of dat file of given format,
+/// unnamed columns are named `Unknown{n}`
+pub fn column_offsets_with_format(
+    columns: &[TableColumn],
+    format: DatFormat,
+) -> Vec<(String, Range<usize>)> {
+    let mut offset = 0;
+    column_names(columns)
+        .into_iter()
+        .zip(columns)
+        .map(|(name, column)| {
+            let start = offset;
+            offset += column_size(column, format);
+            (name, start..offset)
+        })
+        .collect()
+}
+
+/// Parses version string like "3.21.2" into its numeric components for comparison
+fn parse_version(version: &str) -> Vec<u32> {
+    let mut parts: Vec<u32> = version
+        .split('.')
+        .map(|part| part.trim().parse().unwrap_or(0))
+        .collect();
+    // "3.21" and "3.21.0" should compare as equal
+    while parts.last() == Some(&0) {
+        parts.pop();
+    }
+    parts
+}
+
+#[derive(Debug, Clone, Copy, serde::Deserialize, serde::Serialize)]
 #[serde(rename_all = "lowercase")]
 pub enum ColumnType {
     Bool,
     String,
     I32,
+    I64,
+    U64,
     F32,
     Array,
     Row,
@@ -70,11 +286,13 @@ pub enum ColumnType {
     EnumRow,
 }
 
-#[derive(Debug, serde::Deserialize)]
+#[derive(Debug, Clone, serde::Deserialize)]
 #[serde(untagged)]
 pub enum Reference {
-    RefUsingRowIndex { table: String },
+    // Untagged variants are tried in order and unknown fields are ignored, so the variant with
+    // more fields has to go first
     RefUsingColumn { table: String, column: String },
+    RefUsingRowIndex { table: String },
 }
 
 #[derive(Debug, serde::Deserialize)]
@@ -83,3 +301,97 @@ pub struct SchemaEnumeration {
     pub indexing: u8,
     pub enumerators: Vec<Option<String>>,
 }
+
+impl SchemaEnumeration {
+    /// Returns name of the enumerator with the given value, taking `indexing` into account.
+    /// Returns None if value is out of range or enumerator has no name
+    pub fn enumerator_name(&self, value: usize) -> Option<&str> {
+        let index = value.checked_sub(self.indexing as usize)?;
+        self.enumerators.get(index)?.as_deref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::temp_dir;
+
+    #[test]
+    fn enumerator_names_use_indexing() {
+        let schema = SchemaFile::read_from_str(
+            r#"{"tables": [], "enumerations": [
+                {"name": "Rarity", "indexing": 1, "enumerators": ["Normal", null, "Rare"]}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(schema.enum_name("Rarity", 0), None);
+        assert_eq!(schema.enum_name("Rarity", 1), Some("Normal"));
+        assert_eq!(schema.enum_name("Rarity", 2), None);
+        assert_eq!(schema.enum_name("Rarity", 3), Some("Rare"));
+        assert_eq!(schema.enum_name("Rarity", 4), None);
+        assert_eq!(schema.enum_name("Missing", 1), None);
+    }
+
+    #[test]
+    fn read_from_file_errors_name_the_path() {
+        let dir = temp_dir("schema-errors");
+        let missing = dir.join("missing.json");
+        let error = SchemaFile::read_from_file(&missing).unwrap_err();
+        assert!(
+            error.to_string().contains(&missing.display().to_string()),
+            "{error}"
+        );
+
+        let invalid = dir.join("invalid.json");
+        std::fs::write(&invalid, "{").unwrap();
+        let error = SchemaFile::read_from_file(&invalid).unwrap_err();
+        assert!(
+            error.to_string().contains(&invalid.display().to_string()),
+            "{error}"
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn find_column_skips_unnamed_columns() {
+        let schema = SchemaFile::from_tables_json(
+            r#"[{"name": "A", "tags": [], "columns": [
+                {"name": null, "array": false, "type": "i32", "unique": false, "localized": false},
+                {"name": "Id", "array": false, "type": "string", "unique": true, "localized": false},
+                {"name": null, "array": false, "type": "bool", "unique": false, "localized": false},
+                {"name": null, "array": true, "type": "i32", "unique": false, "localized": false},
+                {"name": "Level", "array": false, "type": "i32", "unique": false, "localized": false}
+            ]}]"#,
+        )
+        .unwrap();
+        let table = schema.find_table("A").unwrap();
+        assert_eq!(table.find_column("Id").map(|(i, _)| i), Some(1));
+        let (index, column) = table.find_column("Level").unwrap();
+        assert_eq!(index, 4);
+        assert_eq!(column.name.as_deref(), Some("Level"));
+        assert!(table.find_column("level").is_none());
+        assert!(table.find_column("Unknown0").is_none());
+        assert!(table.find_column("").is_none());
+    }
+
+    #[test]
+    fn find_table_sees_changed_tables() {
+        let mut schema = SchemaFile::from_tables_json(
+            r#"[{"name": "Mods", "tags": [], "columns": []},
+                {"name": "Stats", "tags": [], "columns": []}]"#,
+        )
+        .unwrap();
+        assert_eq!(schema.find_table("mods").unwrap().name, "Mods");
+        assert_eq!(schema.find_table("STATS").unwrap().name, "Stats");
+        assert!(schema.find_table("Tags").is_none());
+
+        // index was built by the calls above
+        let tables = schema.tables_mut();
+        tables.swap(0, 1);
+        tables[1].name = "Tags".to_string();
+        assert_eq!(schema.find_table("stats").unwrap().name, "Stats");
+        assert_eq!(schema.find_table("tags").unwrap().name, "Tags");
+        assert!(schema.find_table("mods").is_none());
+    }
+}
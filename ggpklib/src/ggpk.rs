@@ -1,4 +1,4 @@
-use std::io;
+use std::{fmt, io};
 
 use byteorder::{LittleEndian, ReadBytesExt};
 
@@ -69,13 +69,35 @@ impl EntryTag {
         if &tag == b"FILE" {
             return Ok(Self::File);
         }
-        unimplemented!("Unknown entry tag: {:?}", String::from_utf8(tag.to_vec()));
+        Err(io::Error::new(io::ErrorKind::InvalidData, UnknownTag(tag)))
     }
 }
 
+/// Error returned when entry has unknown tag, contains raw bytes of the tag, it can be
+/// retrieved from `io::Error` using `get_ref` and `downcast_ref`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnknownTag(pub [u8; 4]);
+
+impl fmt::Display for UnknownTag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "unknown entry tag: {:?} ({})",
+            self.0,
+            String::from_utf8_lossy(&self.0)
+        )
+    }
+}
+
+impl std::error::Error for UnknownTag {}
+
 #[derive(Debug, Clone)]
 pub enum EntryData {
-    Free,
+    /// Unused space, free entries form a linked list starting from the GGPK entry
+    Free {
+        /// Offset of the next free entry, 0 if this is the last one
+        next: u64,
+    },
     Pdir {
         name_length: u32,
         total_entries: u32,
@@ -97,7 +119,10 @@ pub enum EntryData {
 impl EntryData {
     pub fn parse(reader: &mut impl io::Read, tag: EntryTag) -> Result<Self, io::Error> {
         Ok(match tag {
-            EntryTag::Free => Self::Free,
+            EntryTag::Free => {
+                let next = reader.read_u64::<LittleEndian>()?;
+                Self::Free { next }
+            }
             EntryTag::Pdir => {
                 let name_length = reader.read_u32::<LittleEndian>()?;
                 let total_entries = reader.read_u32::<LittleEndian>()?;
@@ -108,7 +133,7 @@ impl EntryData {
                 reader.read_exact(&mut name_buf)?;
                 let vecu16: Vec<u16> = name_buf
                     .chunks_exact(2)
-                    .map(|a| u16::from_ne_bytes([a[0], a[1]]))
+                    .map(|a| u16::from_le_bytes([a[0], a[1]]))
                     .collect();
                 let sliceu16 = vecu16.as_slice();
                 let name = String::from_utf16_lossy(sliceu16)
@@ -157,6 +182,29 @@ impl EntryData {
     }
 }
 
+impl EntryData {
+    /// Returns name of the directory or file entry
+    pub fn name(&self) -> Option<&str> {
+        match self {
+            Self::Pdir { name, .. } | Self::File { name, .. } => Some(name),
+            Self::Free { .. } | Self::Ggpk { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for EntryData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Free { next } => write!(f, "FREE next={next}"),
+            Self::Pdir { name, entries, .. } => {
+                write!(f, "PDIR \"{name}\" entries={}", entries.len())
+            }
+            Self::File { name, .. } => write!(f, "FILE \"{name}\""),
+            Self::Ggpk { version, .. } => write!(f, "GGPK version={version}"),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct DirectoryEntry {
     pub entry_name_hash: i32,
@@ -164,6 +212,20 @@ pub struct DirectoryEntry {
 }
 
 impl DirectoryEntry {
+    /// Computes hash of the entry name as it is stored in `entry_name_hash`, names are hashed
+    /// lowercase, encoded as UTF-32 in GGPK version 4 and as UTF-16 in older versions
+    pub fn name_hash(name: &str, version: u32) -> i32 {
+        let name = name.to_lowercase();
+        let bytes: Vec<u8> = if version == 4 {
+            name.chars()
+                .flat_map(|c| (c as u32).to_le_bytes())
+                .collect()
+        } else {
+            name.encode_utf16().flat_map(|c| c.to_le_bytes()).collect()
+        };
+        murmur2::murmur2(&bytes, 0) as i32
+    }
+
     pub fn parse(reader: &mut impl io::Read) -> Result<Self, io::Error> {
         let entry_name_hash = reader.read_i32::<LittleEndian>()?;
         let offset = reader.read_u64::<LittleEndian>()?;
@@ -173,3 +235,36 @@ impl DirectoryEntry {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn free_entry_has_next_offset() {
+        let mut bytes = 16u32.to_le_bytes().to_vec();
+        bytes.extend(b"FREE");
+        bytes.extend(0x1234_5678_9abcu64.to_le_bytes());
+        let entry = Entry::parse(&mut bytes.as_slice()).unwrap();
+        assert_eq!(entry.length, 16);
+        assert_eq!(entry.tag, EntryTag::Free);
+        assert!(matches!(
+            entry.data,
+            EntryData::Free {
+                next: 0x1234_5678_9abc
+            }
+        ));
+        assert_eq!(entry.data.to_string(), "FREE next=20015998343868");
+    }
+
+    #[test]
+    fn unknown_tag_is_error() {
+        let mut bytes = 16u32.to_le_bytes().to_vec();
+        bytes.extend(b"NOPE");
+        bytes.extend(0u64.to_le_bytes());
+        let error = Entry::parse(&mut bytes.as_slice()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        let tag = error.get_ref().unwrap().downcast_ref::<UnknownTag>();
+        assert_eq!(tag, Some(&UnknownTag(*b"NOPE")));
+    }
+}
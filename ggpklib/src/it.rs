@@ -24,7 +24,7 @@ static KEY_VALUE_REGEX: Lazy<Regex> = Lazy::new(|| {
         .unwrap()
 });
 
-#[derive(Debug, Clone, serde::Serialize)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct ITFile {
     pub version: u8,
     pub aabstract: bool,
@@ -54,19 +54,13 @@ impl ITFile {
                     .as_str()
                     .trim_matches('"')
                     .to_string();
-                if section_key == "Base" && key == "tag" {
-                    let entry = section_map
-                        .entry(key)
-                        .or_insert_with(|| ITValue::Set(BTreeSet::new()));
-                    match entry {
-                        ITValue::Set(set) => {
-                            set.insert(ITValue::new(value));
-                        }
-                        _ => unreachable!(),
-                    }
+                if is_list_key(&section_key, &key) && !section_map.contains_key(&key) {
+                    section_map.insert(key, ITValue::new_list(value));
+                } else if let Some(existing) = section_map.remove(&key) {
+                    // repeated keys are collected into a set
+                    section_map.insert(key, existing.union(ITValue::new(value)));
                 } else {
-                    let value = ITValue::new(value);
-                    section_map.insert(key, value);
+                    section_map.insert(key, ITValue::new(value));
                 }
             }
 
@@ -81,27 +75,79 @@ impl ITFile {
         }
     }
 
+    /// Gets the value of `key` in `section`
+    pub fn get(&self, section: &str, key: &str) -> Option<&ITValue> {
+        self.sections.get(section)?.get(key)
+    }
+
+    /// Gets the value of `key` in `section` as a string, returns None if value is missing or is
+    /// not a ITValue::String variant
+    pub fn get_string(&self, section: &str, key: &str) -> Option<&str> {
+        match self.get(section, key)? {
+            ITValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Gets the value of `key` in `section` as an i32, returns None if value is missing or is
+    /// not a ITValue::Number variant
+    pub fn get_number(&self, section: &str, key: &str) -> Option<i32> {
+        match self.get(section, key)? {
+            ITValue::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    /// Gets the value of `key` in `section` as a set, returns None if value is missing or is
+    /// not a ITValue::Set variant
+    pub fn get_set(&self, section: &str, key: &str) -> Option<&BTreeSet<ITValue>> {
+        match self.get(section, key)? {
+            ITValue::Set(s) => Some(s),
+            _ => None,
+        }
+    }
+
     /// Merges two ITFile's
     ///
-    /// If value keys exists in both ITFile then the value from `self` will be used, unless the
-    /// type of the value is ITValue::Set, in which case the values from `other` will be added to
-    /// the set
+    /// If value keys exists in both ITFile then the value from `self` overrides the value from
+    /// `other`, unless either of the values is ITValue::Set, in which case both values are
+    /// unioned into a single set. This applies to every section, not only to `Base`
+    ///
+    /// Values listed in a remove key of `self`, like `remove_tag`, are removed from the list
+    /// key of `other` before the union, so they are not inherited, see `REMOVE_KEYS`
     pub fn merge(mut self, other: Self) -> Self {
-        for (section_key, section_map) in other.sections {
+        for (section_key, mut section_map) in other.sections {
             let Some(self_section) = self.sections.get_mut(&section_key) else {
                 self.sections.insert(section_key, section_map);
                 continue;
             };
+            for (_, remove_key, list_key) in
+                REMOVE_KEYS.iter().filter(|(s, _, _)| *s == section_key)
+            {
+                let (Some(removed), Some(ITValue::Set(inherited))) = (
+                    self_section.get(*remove_key),
+                    section_map.get_mut(*list_key),
+                ) else {
+                    continue;
+                };
+                for value in removed.clone().into_set() {
+                    inherited.remove(&value);
+                }
+            }
             for (key, value) in section_map {
-                if let Some(existing_value) = self_section.get_mut(&key) {
-                    match (existing_value, value) {
-                        (ITValue::Set(self_set), ITValue::Set(other_set)) => {
-                            self_set.extend(other_set);
-                        }
-                        _ => (),
+                match self_section.remove(&key) {
+                    Some(existing)
+                        if matches!(existing, ITValue::Set(_))
+                            || matches!(value, ITValue::Set(_)) =>
+                    {
+                        self_section.insert(key, existing.union(value));
+                    }
+                    Some(existing) => {
+                        self_section.insert(key, existing);
+                    }
+                    None => {
+                        self_section.insert(key, value);
                     }
-                } else {
-                    self_section.insert(key, value);
                 }
             }
         }
@@ -114,7 +160,21 @@ impl ITFile {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, serde::Serialize)]
+/// Keys that are always parsed as ITValue::Set, even if they appear only once, as
+/// `(section, key)` pairs. Keys that are repeated inside a section are parsed as sets as well
+const LIST_KEYS: &[(&str, &str)] = &[("Base", "tag"), ("Base", "remove_tag")];
+
+/// Keys removing values from a list key inherited from the parent, as
+/// `(section, remove key, list key)` triples, see `ITFile::merge`
+const REMOVE_KEYS: &[(&str, &str, &str)] = &[("Base", "remove_tag", "tag")];
+
+fn is_list_key(section: &str, key: &str) -> bool {
+    LIST_KEYS.iter().any(|(s, k)| *s == section && *k == key)
+}
+
+#[derive(
+    Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
 pub enum ITValue {
     Number(i32),
     Set(BTreeSet<ITValue>),
@@ -134,6 +194,20 @@ impl ITValue {
         Self::Set(BTreeSet::from([Self::new(string)]))
     }
 
+    /// Combines two values into a set, values that are not sets are added as set elements
+    fn union(self, other: Self) -> Self {
+        let mut set = self.into_set();
+        set.extend(other.into_set());
+        Self::Set(set)
+    }
+
+    fn into_set(self) -> BTreeSet<ITValue> {
+        match self {
+            Self::Set(s) => s,
+            value => BTreeSet::from([value]),
+        }
+    }
+
     /// Gets the value as a string
     ///
     /// # Panics:
@@ -176,3 +250,62 @@ impl ITValue {
         self.as_set().iter().map(|x| f(&x)).collect()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn it_file(sections: &str) -> ITFile {
+        ITFile::parse(format!("version 2\nextends \"nothing\"\n\n{sections}"))
+    }
+
+    fn strings(values: &[&str]) -> BTreeSet<ITValue> {
+        values
+            .iter()
+            .map(|v| ITValue::String(v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn list_and_repeated_keys_are_sets() {
+        let file = it_file(
+            "Base\n{\n\ttag = \"a\"\n\tname = \"x\"\n}\n\
+             Stats\n{\n\tstat = \"s1\"\n\tstat = \"s2\"\n\tlevel = 5\n}\n",
+        );
+        assert_eq!(file.get_set("Base", "tag"), Some(&strings(&["a"])));
+        assert_eq!(file.get_string("Base", "name"), Some("x"));
+        assert_eq!(file.get_set("Stats", "stat"), Some(&strings(&["s1", "s2"])));
+        assert_eq!(file.get_number("Stats", "level"), Some(5));
+    }
+
+    #[test]
+    fn merge_overrides_values_and_unions_sets() {
+        let child = it_file(
+            "Base\n{\n\ttag = \"b\"\n\tname = \"child\"\n}\n\
+             Stats\n{\n\tstat = \"s2\"\n\tstat = \"s3\"\n}\n",
+        );
+        let parent = it_file(
+            "Base\n{\n\ttag = \"a\"\n\tname = \"parent\"\n\tsize = 2\n}\n\
+             Stats\n{\n\tstat = \"s1\"\n}\nSockets\n{\n\tcount = 3\n}\n",
+        );
+        let merged = child.merge(parent);
+        assert_eq!(merged.get_set("Base", "tag"), Some(&strings(&["a", "b"])));
+        assert_eq!(merged.get_string("Base", "name"), Some("child"));
+        assert_eq!(merged.get_number("Base", "size"), Some(2));
+        assert_eq!(
+            merged.get_set("Stats", "stat"),
+            Some(&strings(&["s1", "s2", "s3"]))
+        );
+        assert_eq!(merged.get_number("Sockets", "count"), Some(3));
+    }
+
+    #[test]
+    fn merge_removes_inherited_tags() {
+        let child =
+            it_file("Base\n{\n\ttag = \"c\"\n\tremove_tag = \"a\"\n\tremove_tag = \"c\"\n}\n");
+        let parent = it_file("Base\n{\n\ttag = \"a\"\n\ttag = \"b\"\n}\n");
+        let merged = child.merge(parent);
+        // only inherited tags are removed, tags of the child stay
+        assert_eq!(merged.get_set("Base", "tag"), Some(&strings(&["b", "c"])));
+    }
+}
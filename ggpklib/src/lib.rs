@@ -7,3 +7,6 @@ pub mod it;
 pub mod poefs;
 pub mod translation;
 pub mod utils;
+
+#[cfg(test)]
+mod test_utils;
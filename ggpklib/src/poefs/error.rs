@@ -0,0 +1,30 @@
+use std::io;
+
+/// Error returned by `PoeFS::get_file`
+#[derive(Debug, thiserror::Error)]
+pub enum PoeFsError {
+    /// Path is not listed in the index bundle or, for sources with files outside of bundles, is
+    /// missing from the source
+    #[error("path not found: {0}")]
+    PathNotFound(String),
+    /// Path is listed in the index bundle, but there is no file record with its hash
+    #[error("path hash {hash:#x} not found in file map: {path}")]
+    HashNotInFileMap { path: String, hash: u64 },
+    /// File record points outside of the bundles of the index or outside of its bundle
+    #[error("invalid file record of {path}: {reason}")]
+    InvalidFileRecord { path: String, reason: String },
+    /// Bundle containing the file is missing from the source
+    #[error("bundle file {bundle}.bundle.bin not found, needed for: {path}")]
+    BundleNotFound { bundle: String, path: String },
+    /// Bundle containing the file failed to decompress
+    #[error("failed to read {path} from bundle {bundle}: {error}")]
+    Decompress {
+        bundle: String,
+        path: String,
+        #[source]
+        error: io::Error,
+    },
+    /// Source failed to read the file or its bundle
+    #[error("{0}")]
+    Source(#[from] anyhow::Error),
+}
@@ -0,0 +1,68 @@
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::bundle::Bundle;
+
+use super::{normalize_path, FileSource};
+
+/// Source that reads game files previously extracted from bundles to a directory, files are
+/// already decompressed so index bundle is not needed and paths are taken from the directory
+pub struct ExtractedSource {
+    root: PathBuf,
+    /// Normalized paths relative to the root mapped to paths on disk
+    files: HashMap<String, PathBuf>,
+}
+
+impl ExtractedSource {
+    /// Creates source from directory containing extracted files, e.g. the one that contains
+    /// `data` and `metadata` directories. Directory is scanned for files once here
+    pub fn new(root: impl AsRef<Path>) -> Result<Self, io::Error> {
+        let root = root.as_ref().to_path_buf();
+        let mut files = HashMap::new();
+        collect_files(&root, &root, &mut files)?;
+        Ok(Self { root, files })
+    }
+
+    /// Returns directory the source reads files from
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+}
+
+fn collect_files(
+    root: &Path,
+    dir: &Path,
+    files: &mut HashMap<String, PathBuf>,
+) -> Result<(), io::Error> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(root, &path, files)?;
+        } else if let Ok(relative) = path.strip_prefix(root) {
+            let relative = relative.to_string_lossy();
+            files.insert(normalize_path(&relative), path);
+        }
+    }
+    Ok(())
+}
+
+impl FileSource for ExtractedSource {
+    fn get_file(&mut self, _path: &str) -> Result<Option<(Bundle, Vec<u8>)>, anyhow::Error> {
+        Ok(None)
+    }
+
+    fn extracted_paths(&mut self) -> Result<Option<Vec<String>>, anyhow::Error> {
+        Ok(Some(self.files.keys().cloned().collect()))
+    }
+
+    fn get_extracted_file(&mut self, path: &str) -> Result<Option<Vec<u8>>, anyhow::Error> {
+        let path = normalize_path(path);
+        match self.files.get(&path) {
+            Some(file_path) => Ok(Some(std::fs::read(file_path)?)),
+            None => Ok(None),
+        }
+    }
+}
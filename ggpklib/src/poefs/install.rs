@@ -0,0 +1,125 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::anyhow;
+
+const STEAM_GAME_DIR: &str = "steamapps/common/Path of Exile";
+const EPIC_MANIFESTS_DIR: &str = "C:/ProgramData/Epic/EpicGamesLauncher/Data/Manifests";
+const STANDALONE_REGISTRY_KEY: &str = r"HKCU\Software\GrindingGearGames\Path of Exile";
+
+/// Searches Steam libraries, standalone client install location and Epic manifests for the
+/// game directory, that is directory containing `Content.ggpk` or `Bundles2`
+///
+/// Returns an error listing searched directories if game is not found
+pub fn find_install_dir() -> Result<PathBuf, anyhow::Error> {
+    let mut candidates = Vec::new();
+    candidates.extend(standalone_dirs());
+    for steam_dir in steam_dirs() {
+        for library in steam_libraries(&steam_dir) {
+            candidates.push(library.join(STEAM_GAME_DIR));
+        }
+    }
+    candidates.extend(epic_dirs());
+
+    if let Some(found) = candidates.iter().find(|dir| is_install_dir(dir)) {
+        return Ok(found.clone());
+    }
+    let searched = candidates
+        .iter()
+        .map(|dir| format!("  {}", dir.display()))
+        .collect::<Vec<_>>()
+        .join("\n");
+    Err(anyhow!(
+        "Path of Exile install directory not found, searched:\n{}",
+        searched
+    ))
+}
+
+fn is_install_dir(dir: &Path) -> bool {
+    dir.join("Content.ggpk").is_file() || dir.join("Bundles2").is_dir()
+}
+
+fn standalone_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Some(dir) = read_registry_value(STANDALONE_REGISTRY_KEY, "InstallLocation") {
+        dirs.push(PathBuf::from(dir));
+    }
+    if cfg!(windows) {
+        dirs.push(PathBuf::from(
+            "C:/Program Files (x86)/Grinding Gear Games/Path of Exile",
+        ));
+    }
+    dirs
+}
+
+fn steam_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Some(dir) = read_registry_value(r"HKCU\Software\Valve\Steam", "SteamPath") {
+        dirs.push(PathBuf::from(dir));
+    }
+    if cfg!(windows) {
+        dirs.push(PathBuf::from("C:/Program Files (x86)/Steam"));
+    }
+    if let Some(home) = std::env::var_os("HOME") {
+        let home = PathBuf::from(home);
+        dirs.push(home.join(".steam/steam"));
+        dirs.push(home.join(".local/share/Steam"));
+    }
+    dirs
+}
+
+/// Returns library directories listed in `steamapps/libraryfolders.vdf`, including Steam
+/// directory itself
+fn steam_libraries(steam_dir: &Path) -> Vec<PathBuf> {
+    let mut libraries = vec![steam_dir.to_path_buf()];
+    let Ok(vdf) = std::fs::read_to_string(steam_dir.join("steamapps/libraryfolders.vdf")) else {
+        return libraries;
+    };
+    for line in vdf.lines() {
+        let mut parts = line.split('"').filter(|p| !p.trim().is_empty());
+        if parts.next() != Some("path") {
+            continue;
+        }
+        if let Some(path) = parts.next() {
+            let path = PathBuf::from(path.replace("\\\\", "/"));
+            if !libraries.contains(&path) {
+                libraries.push(path);
+            }
+        }
+    }
+    libraries
+}
+
+/// Returns install locations of Path of Exile from Epic Games Launcher manifests
+fn epic_dirs() -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(EPIC_MANIFESTS_DIR) else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "item"))
+        .filter_map(|entry| std::fs::read_to_string(entry.path()).ok())
+        .filter_map(|content| serde_json::from_str::<serde_json::Value>(&content).ok())
+        .filter(|manifest| manifest["DisplayName"] == "Path of Exile")
+        .filter_map(|manifest| manifest["InstallLocation"].as_str().map(PathBuf::from))
+        .collect()
+}
+
+#[cfg(windows)]
+fn read_registry_value(key: &str, value: &str) -> Option<String> {
+    let output = std::process::Command::new("reg")
+        .args(["query", key, "/v", value])
+        .output()
+        .ok()?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    // output line looks like: "    InstallLocation    REG_SZ    C:\Games\Path of Exile"
+    stdout
+        .lines()
+        .find(|line| line.trim_start().starts_with(value))
+        .and_then(|line| line.split_once("REG_SZ"))
+        .map(|(_, data)| data.trim().to_string())
+}
+
+#[cfg(not(windows))]
+fn read_registry_value(_key: &str, _value: &str) -> Option<String> {
+    None
+}
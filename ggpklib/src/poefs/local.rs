@@ -1,19 +1,28 @@
 use std::{
+    collections::{HashMap, HashSet},
     fs::File,
-    io::{self, Read, SeekFrom},
+    io::{self, Cursor, Read, Seek, SeekFrom},
     path::Path,
 };
 
+use anyhow::anyhow;
+use sha2::{Digest, Sha256};
+
 use crate::{
     bundle::Bundle,
-    ggpk::{Entry, EntryData},
+    ggpk::{DirectoryEntry, Entry, EntryData},
 };
 
-use super::FileSource;
+use super::{find_install_dir, normalize_path, FileSource};
 
 pub struct LocalSource {
     file: File,
     ggpk_entry: Entry,
+    /// Normalized paths of files outside of `Bundles2` mapped to offsets of their entries, read
+    /// when they are requested first time
+    loose_files: Option<HashMap<String, u64>>,
+    /// Check SHA256 hashes of read files, see `with_verification`
+    verify: bool,
 }
 
 impl LocalSource {
@@ -23,53 +32,195 @@ impl LocalSource {
         Ok(Self {
             file,
             ggpk_entry: entry,
+            loose_files: None,
+            verify: false,
         })
     }
 
+    /// Enables checking SHA256 hash of every read file against the hash stored in its entry,
+    /// reading a file with mismatching hash returns an error. Disabled by default
+    pub fn with_verification(mut self, verify: bool) -> Self {
+        self.verify = verify;
+        self
+    }
+
+    /// Reads data of the file entry, file has to be positioned right after the entry header
+    fn read_file_data(&mut self, entry: &Entry) -> Result<Vec<u8>, io::Error> {
+        let mut buf = vec![0u8; entry.data_length_left() as usize];
+        self.file.read_exact(&mut buf)?;
+        if let EntryData::File {
+            sha256hash, name, ..
+        } = &entry.data
+        {
+            if self.verify && Sha256::digest(&buf).as_slice() != sha256hash {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("SHA256 hash of {name} doesn't match hash of its entry"),
+                ));
+            }
+        }
+        Ok(buf)
+    }
+
+    /// Opens `Content.ggpk` from automatically detected game install directory
+    pub fn from_install() -> Result<Self, anyhow::Error> {
+        let dir = find_install_dir()?;
+        let ggpk = dir.join("Content.ggpk");
+        if !ggpk.is_file() {
+            return Err(anyhow!(
+                "install directory {} has no Content.ggpk",
+                dir.display()
+            ));
+        }
+        Ok(Self::new(ggpk)?)
+    }
+
+    /// Walks the list of free entries and returns total size of them in bytes, that is space
+    /// that can be reclaimed by defragmenting the file
+    pub fn free_space(&mut self) -> Result<u64, io::Error> {
+        let EntryData::Ggpk { entries, .. } = &self.ggpk_entry.data else {
+            return Ok(0);
+        };
+        let mut offset = 0;
+        for entry in entries {
+            self.file.seek(SeekFrom::Start(entry.offset))?;
+            if let EntryData::Free { .. } = Entry::parse(&mut self.file)?.data {
+                offset = entry.offset;
+            }
+        }
+
+        let mut total = 0;
+        let mut visited = HashSet::new();
+        while offset != 0 && visited.insert(offset) {
+            self.file.seek(SeekFrom::Start(offset))?;
+            let entry = Entry::parse(&mut self.file)?;
+            let EntryData::Free { next } = entry.data else {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("free list points to non free entry at {offset}"),
+                ));
+            };
+            total += entry.length as u64;
+            offset = next;
+        }
+        Ok(total)
+    }
+
+    /// Returns version of the GGPK file
+    fn version(&self) -> u32 {
+        match &self.ggpk_entry.data {
+            EntryData::Ggpk { version, .. } => *version,
+            _ => 0,
+        }
+    }
+
+    /// Returns files stored outside of `Bundles2` directory, walks the directory tree on first
+    /// call
+    fn loose_files(&mut self) -> Result<&HashMap<String, u64>, io::Error> {
+        if self.loose_files.is_none() {
+            let mut files = HashMap::new();
+            if let EntryData::Ggpk { entries, .. } = &self.ggpk_entry.data {
+                for ggpk_entry in entries.clone() {
+                    Self::collect_loose_files(&mut self.file, ggpk_entry.offset, "", &mut files)?;
+                }
+            }
+            self.loose_files = Some(files);
+        }
+        Ok(self.loose_files.as_ref().unwrap())
+    }
+
+    fn collect_loose_files(
+        reader: &mut (impl io::Read + io::Seek),
+        offset: u64,
+        parent: &str,
+        files: &mut HashMap<String, u64>,
+    ) -> Result<(), io::Error> {
+        reader.seek(SeekFrom::Start(offset))?;
+        let entry = Entry::parse(reader)?;
+        match &entry.data {
+            EntryData::Pdir { name, entries, .. } => {
+                let path = match (parent, name.as_str()) {
+                    ("", name) => name.to_string(),
+                    (parent, "") => parent.to_string(),
+                    (parent, name) => format!("{parent}/{name}"),
+                };
+                if path.eq_ignore_ascii_case("Bundles2") {
+                    return Ok(());
+                }
+                for child in entries {
+                    Self::collect_loose_files(reader, child.offset, &path, files)?;
+                }
+            }
+            EntryData::File { name, .. } => {
+                let path = if parent.is_empty() {
+                    name.clone()
+                } else {
+                    format!("{parent}/{name}")
+                };
+                files.insert(normalize_path(&path), offset);
+            }
+            EntryData::Free { .. } | EntryData::Ggpk { .. } => {}
+        }
+        Ok(())
+    }
+
     fn find_file_helper(
         entry: &Entry,
         reader: &mut (impl io::Read + io::Seek),
         mut path: &[&str],
-    ) -> Option<Entry> {
+        version: u32,
+    ) -> Result<Option<Entry>, io::Error> {
         if path.is_empty() {
-            return None;
+            return Ok(None);
         }
 
         match &entry.data {
-            EntryData::Free => None,
+            EntryData::Free { .. } => Ok(None),
             EntryData::Pdir { name, entries, .. } => {
                 if name != path[0] {
-                    return None;
+                    return Ok(None);
                 }
                 path = &path[1..];
-                for entry in entries {
-                    reader.seek(SeekFrom::Start(entry.offset)).unwrap();
-                    let entry = Entry::parse(reader).unwrap();
-                    let found_file = Self::find_file_helper(&entry, reader, path);
+                let Some(child_name) = path.first() else {
+                    return Ok(None);
+                };
+                // only parse children with matching name hash, unless none of them match
+                let hash = DirectoryEntry::name_hash(child_name, version);
+                let mut candidates: Vec<_> = entries
+                    .iter()
+                    .filter(|e| e.entry_name_hash == hash)
+                    .collect();
+                if candidates.is_empty() {
+                    candidates = entries.iter().collect();
+                }
+                for entry in candidates {
+                    reader.seek(SeekFrom::Start(entry.offset))?;
+                    let entry = Entry::parse(reader)?;
+                    let found_file = Self::find_file_helper(&entry, reader, path, version)?;
                     if found_file.is_some() {
-                        return found_file;
+                        return Ok(found_file);
                     }
                 }
-                None
+                Ok(None)
             }
             EntryData::File { name, .. } => {
                 if name == path[0] {
-                    Some(entry.clone())
+                    Ok(Some(entry.clone()))
                 } else {
-                    None
+                    Ok(None)
                 }
             }
             EntryData::Ggpk { entries, .. } => {
-                reader.seek(SeekFrom::Start(entries[0].offset)).unwrap();
-                let entry = Entry::parse(reader).unwrap();
-                let found_file = Self::find_file_helper(&entry, reader, path);
+                reader.seek(SeekFrom::Start(entries[0].offset))?;
+                let entry = Entry::parse(reader)?;
+                let found_file = Self::find_file_helper(&entry, reader, path, version)?;
                 if found_file.is_some() {
-                    return found_file;
+                    return Ok(found_file);
                 }
 
-                reader.seek(SeekFrom::Start(entries[1].offset)).unwrap();
-                let entry = Entry::parse(reader).unwrap();
-                Self::find_file_helper(&entry, reader, path)
+                reader.seek(SeekFrom::Start(entries[1].offset))?;
+                let entry = Entry::parse(reader)?;
+                Self::find_file_helper(&entry, reader, path, version)
             }
         }
     }
@@ -78,11 +229,96 @@ impl LocalSource {
 impl FileSource for LocalSource {
     fn get_file(&mut self, path: &str) -> Result<Option<(Bundle, Vec<u8>)>, anyhow::Error> {
         let vec = path.split('/').collect::<Vec<_>>();
-        let _file_entry = Self::find_file_helper(&self.ggpk_entry, &mut self.file, &vec).unwrap();
-        let bundle = Bundle::parse(&mut self.file)?;
-        let size = bundle.total_payload_size;
-        let mut buf = vec![0u8; size as usize];
-        self.file.read_exact(&mut buf)?;
+        let version = self.version();
+        let Some(file_entry) =
+            Self::find_file_helper(&self.ggpk_entry, &mut self.file, &vec, version)?
+        else {
+            return Ok(None);
+        };
+        let data = self.read_file_data(&file_entry)?;
+        let mut c = Cursor::new(data);
+        let bundle = Bundle::parse(&mut c)?;
+        let position = c.position() as usize;
+        let mut data = c.into_inner();
+        let buf = data.split_off(position);
         Ok(Some((bundle, buf)))
     }
+
+    fn loose_paths(&mut self) -> Result<Vec<String>, anyhow::Error> {
+        Ok(self.loose_files()?.keys().cloned().collect())
+    }
+
+    fn get_extracted_file(&mut self, path: &str) -> Result<Option<Vec<u8>>, anyhow::Error> {
+        let path = normalize_path(path);
+        let Some(&offset) = self.loose_files()?.get(&path) else {
+            return Ok(None);
+        };
+        self.file.seek(SeekFrom::Start(offset))?;
+        let entry = Entry::parse(&mut self.file)?;
+        Ok(Some(self.read_file_data(&entry)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{bundle, ggpk, temp_dir, Node};
+
+    #[test]
+    fn read_file_data_checks_hash() {
+        let dir = temp_dir("verify");
+        let path = dir.join("Content.ggpk");
+        let mut bytes = ggpk(vec![Node::File("a.txt", b"hello")]);
+        std::fs::write(&path, &bytes).unwrap();
+        let mut source = LocalSource::new(&path).unwrap().with_verification(true);
+        assert_eq!(
+            source.get_extracted_file("a.txt").unwrap().unwrap(),
+            b"hello"
+        );
+
+        // corrupt the data after its hash was written
+        let position = bytes.windows(5).position(|w| w == b"hello").unwrap();
+        bytes[position] = b'j';
+        std::fs::write(&path, &bytes).unwrap();
+        let mut source = LocalSource::new(&path).unwrap().with_verification(true);
+        let error = source.get_extracted_file("a.txt").unwrap_err();
+        let error = error.downcast_ref::<io::Error>().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        let mut source = LocalSource::new(&path).unwrap();
+        assert_eq!(
+            source.get_extracted_file("a.txt").unwrap().unwrap(),
+            b"jello"
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn free_space_sums_free_entries() {
+        let dir = temp_dir("free-space");
+        let path = dir.join("Content.ggpk");
+        std::fs::write(&path, ggpk(vec![Node::File("a.txt", b"hello")])).unwrap();
+        let mut source = LocalSource::new(&path).unwrap();
+        assert_eq!(source.free_space().unwrap(), 16);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn missing_bundles_are_none() {
+        let dir = temp_dir("missing-bundle");
+        let path = dir.join("Content.ggpk");
+        let bundle = bundle(b"data");
+        let nodes = vec![Node::Dir(
+            "Bundles2",
+            vec![Node::File("a.bundle.bin", &bundle)],
+        )];
+        std::fs::write(&path, ggpk(nodes)).unwrap();
+        let mut source = LocalSource::new(&path).unwrap();
+        assert!(source.get_file("/Bundles2/a.bundle.bin").unwrap().is_some());
+        assert!(source.get_file("/Bundles2/b.bundle.bin").unwrap().is_none());
+        assert!(source.get_file("/Other/a.bundle.bin").unwrap().is_none());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -0,0 +1,49 @@
+use std::{
+    fs::File,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+use crate::bundle::Bundle;
+
+use super::{find_install_dir, FileSource};
+
+/// Source that reads bundles from game directory where `Bundles2` is stored as loose files
+/// instead of inside of `Content.ggpk`, like in Steam installs
+pub struct LooseFilesSource {
+    root: PathBuf,
+}
+
+impl LooseFilesSource {
+    /// Creates source from game directory, directory that contains `Bundles2`
+    pub fn new(root: impl AsRef<Path>) -> Result<Self, io::Error> {
+        let root = root.as_ref().to_path_buf();
+        if !root.join("Bundles2").is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} has no Bundles2 directory", root.display()),
+            ));
+        }
+        Ok(Self { root })
+    }
+
+    /// Creates source from automatically detected game install directory
+    pub fn from_install() -> Result<Self, anyhow::Error> {
+        Ok(Self::new(find_install_dir()?)?)
+    }
+}
+
+impl FileSource for LooseFilesSource {
+    fn get_file(&mut self, path: &str) -> Result<Option<(Bundle, Vec<u8>)>, anyhow::Error> {
+        let file_path = self.root.join(path.trim_start_matches('/'));
+        let mut file = match File::open(file_path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let bundle = Bundle::parse(&mut file)?;
+        let mut buf = Vec::with_capacity(bundle.total_payload_size as usize);
+        file.read_to_end(&mut buf)?;
+        Ok(Some((bundle, buf)))
+    }
+}
@@ -0,0 +1,52 @@
+use std::{
+    collections::HashMap,
+    io::{self, Cursor, Read},
+};
+
+use crate::bundle::Bundle;
+
+use super::FileSource;
+
+/// Source that serves bundles from memory, useful for testing code that needs `PoeFS` without
+/// a GGPK file or network access
+#[derive(Debug, Default)]
+pub struct MemorySource {
+    files: HashMap<String, (Bundle, Vec<u8>)>,
+}
+
+impl MemorySource {
+    /// Creates source from parsed bundles and their compressed data, keys are paths like
+    /// `/Bundles2/_.index.bin`
+    pub fn new(files: HashMap<String, (Bundle, Vec<u8>)>) -> Self {
+        Self { files }
+    }
+
+    /// Creates source from raw bundle files, keys are paths like `/Bundles2/_.index.bin`
+    pub fn from_bundle_bytes(files: HashMap<String, Vec<u8>>) -> Result<Self, io::Error> {
+        let mut source = Self::default();
+        for (path, bytes) in files {
+            source.insert_bundle_bytes(path, bytes)?;
+        }
+        Ok(source)
+    }
+
+    /// Adds raw bundle file to the source, replacing existing file with the same path
+    pub fn insert_bundle_bytes(
+        &mut self,
+        path: impl Into<String>,
+        bytes: Vec<u8>,
+    ) -> Result<(), io::Error> {
+        let mut c = Cursor::new(bytes);
+        let bundle = Bundle::parse(&mut c)?;
+        let mut data = Vec::new();
+        c.read_to_end(&mut data)?;
+        self.files.insert(path.into(), (bundle, data));
+        Ok(())
+    }
+}
+
+impl FileSource for MemorySource {
+    fn get_file(&mut self, path: &str) -> Result<Option<(Bundle, Vec<u8>)>, anyhow::Error> {
+        Ok(self.files.get(path).cloned())
+    }
+}
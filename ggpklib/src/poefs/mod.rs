@@ -1,20 +1,94 @@
+mod error;
+mod extracted;
+mod install;
 mod local;
+mod loose;
+mod memory;
 mod online;
 
+#[cfg(feature = "rayon")]
+use std::sync::mpsc;
 use std::{
-    collections::HashMap,
+    collections::{BTreeSet, HashMap, HashSet, VecDeque},
     io::{self, BufRead, Cursor},
+    path::Path,
 };
 
 use anyhow::anyhow;
 use byteorder::{LittleEndian, ReadBytesExt};
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+use regex::Regex;
 
-use crate::{bundle::Bundle, bundle_index::BundleIndex, dat::DatFile, it::ITFile};
+use crate::{
+    bundle::Bundle,
+    bundle_index::BundleIndex,
+    dat::{DatFile, DatFormat, DatValue},
+    dat_schema::{Reference, SchemaFile, TableColumn},
+    it::ITFile,
+    utils::decode_utf16,
+};
+pub use error::PoeFsError;
+pub use extracted::ExtractedSource;
+pub use install::find_install_dir;
 pub use local::LocalSource;
-pub use online::OnlineSource;
+pub use loose::LooseFilesSource;
+pub use memory::MemorySource;
+#[cfg(feature = "async")]
+pub use online::AsyncOnlineSource;
+pub use online::{OnlineSource, OnlineSourceBuilder};
+
+/// Seed used by the game to hash paths in the index bundle
+pub const PATH_HASH_SEED: u64 = 0x1337b33f;
+
+/// Default maximum total size of decompressed bundles kept by `PoeFS::get_file`, in bytes
+pub const DEFAULT_BUNDLE_CACHE_SIZE: usize = 256 * 1024 * 1024;
+
+/// Maximum length of .it files `extends` chain
+pub const MAX_IT_DEPTH: usize = 64;
 
 pub trait FileSource {
     fn get_file(&mut self, path: &str) -> Result<Option<(Bundle, Vec<u8>)>, anyhow::Error>;
+
+    /// Returns paths of all game files if the source stores them already decompressed instead
+    /// of in bundles, in that case index bundle is not read and files are read with
+    /// `get_extracted_file`
+    fn extracted_paths(&mut self) -> Result<Option<Vec<String>>, anyhow::Error> {
+        Ok(None)
+    }
+
+    /// Returns paths of files stored outside of bundles next to bundled ones, like loose files
+    /// in `Content.ggpk`, they are read with `get_extracted_file`
+    fn loose_paths(&mut self) -> Result<Vec<String>, anyhow::Error> {
+        Ok(Vec::new())
+    }
+
+    /// Returns decompressed game file stored outside of bundles, used for paths returned by
+    /// `extracted_paths` or `loose_paths`
+    fn get_extracted_file(&mut self, _path: &str) -> Result<Option<Vec<u8>>, anyhow::Error> {
+        Ok(None)
+    }
+}
+
+/// Async counterpart of `FileSource` for sources that fetch files over the network. Takes
+/// `&self` so many files can be fetched concurrently from the same source
+#[cfg(feature = "async")]
+pub trait AsyncFileSource {
+    fn get_file(
+        &self,
+        path: &str,
+    ) -> impl std::future::Future<Output = Result<Option<(Bundle, Vec<u8>)>, anyhow::Error>> + Send;
+}
+
+/// Location of the file in bundles, see `PoeFS::file_info`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileInfo {
+    /// Name of the bundle, without `.bundle.bin` extension
+    pub bundle: String,
+    /// Offset of the file in uncompressed bundle
+    pub offset: u32,
+    /// Uncompressed size of the file
+    pub size: u32,
 }
 
 pub struct PoeFS {
@@ -22,7 +96,18 @@ pub struct PoeFS {
     bundle_index: BundleIndex,
     paths: HashMap<String, u64>,
     file_map: HashMap<u64, usize>,
+    /// Source stores decompressed files, bundle index is empty
+    extracted: bool,
+    duplicate_paths: Vec<String>,
+    hash_collisions: Vec<(String, String)>,
+    /// Paths of files stored outside of bundles, see `include_loose_files`
+    loose_paths: HashSet<String>,
 
+    /// Decompressed bundles by name, see `set_bundle_cache_size`
+    bundle_cache: HashMap<String, Vec<u8>>,
+    /// Names of cached bundles from the least to the most recently added
+    bundle_cache_order: VecDeque<String>,
+    bundle_cache_size: usize,
     dat_cache: HashMap<String, DatFile>,
     txt_cache: HashMap<String, String>,
     it_cache: HashMap<String, ITFile>,
@@ -30,24 +115,95 @@ pub struct PoeFS {
 }
 
 impl PoeFS {
-    pub fn new<S: FileSource + 'static>(mut source: S) -> Self {
-        let (bundle, file) = source.get_file("/Bundles2/_.index.bin").unwrap().unwrap();
+    /// Creates file system from the source
+    ///
+    /// # Panics:
+    /// If index bundle can't be read or parsed, use `try_new` to handle the error
+    pub fn new<S: FileSource + 'static>(source: S) -> Self {
+        Self::try_new(source).unwrap()
+    }
+
+    /// Creates file system from the source, returns an error if index bundle can't be read or
+    /// parsed
+    pub fn try_new<S: FileSource + 'static>(source: S) -> Result<Self, anyhow::Error> {
+        Self::try_new_with_seed(source, PATH_HASH_SEED)
+    }
+
+    /// Same as `try_new` but paths are hashed with `seed` instead of `PATH_HASH_SEED`, for packs
+    /// that use a different seed
+    pub fn try_new_with_seed<S: FileSource + 'static>(
+        mut source: S,
+        seed: u64,
+    ) -> Result<Self, anyhow::Error> {
+        if let Some(extracted_paths) = source.extracted_paths()? {
+            let paths = extracted_paths
+                .iter()
+                .map(|path| normalize_path(path))
+                .collect();
+            return Ok(Self::with_index(
+                Box::new(source),
+                BundleIndex::default(),
+                paths,
+                seed,
+                true,
+            ));
+        }
+
+        let (bundle, file) = source
+            .get_file("/Bundles2/_.index.bin")?
+            .ok_or(anyhow!("index bundle not found"))?;
         let mut c = Cursor::new(file);
-        let uncompressed = bundle.data(&mut c).unwrap();
+        let uncompressed = bundle.data(&mut c)?;
         let mut data = Cursor::new(uncompressed);
-        let bundle_index = BundleIndex::parse(&mut data).unwrap();
+        let bundle_index = BundleIndex::parse(&mut data)?;
 
-        let mut paths = HashMap::new();
+        let mut paths = Vec::new();
         for path_rep in &bundle_index.path_rep {
             let start = path_rep.payload_offset as usize;
             let end = start + path_rep.payload_size as usize;
-            let payload = &bundle_index.path_rep_data[start..end];
+            let payload = bundle_index
+                .path_rep_data
+                .get(start..end)
+                .ok_or(anyhow!("path rep payload is out of bounds"))?;
             let mut c = Cursor::new(payload);
-            for path in make_paths(&mut c).unwrap() {
-                let hash = murmur2::murmur64a(path.as_bytes(), 0x1337b33f);
-                paths.insert(path, hash);
+            paths.extend(make_paths(&mut c)?);
+        }
+
+        Ok(Self::with_index(
+            Box::new(source),
+            bundle_index,
+            paths,
+            seed,
+            false,
+        ))
+    }
+
+    fn with_index(
+        source: Box<dyn FileSource>,
+        bundle_index: BundleIndex,
+        paths: Vec<String>,
+        seed: u64,
+        extracted: bool,
+    ) -> Self {
+        let hashed_paths = paths
+            .into_iter()
+            .map(|path| {
+                let hash = Self::hash_path_with_seed(&path, seed);
+                (path, hash)
+            })
+            .collect::<Vec<_>>();
+
+        let mut duplicate_paths = Vec::new();
+        let mut hash_collisions = Vec::new();
+        let mut seen: HashMap<u64, &str> = HashMap::with_capacity(hashed_paths.len());
+        for (path, hash) in &hashed_paths {
+            match seen.insert(*hash, path) {
+                Some(previous) if previous == path => duplicate_paths.push(path.clone()),
+                Some(previous) => hash_collisions.push((previous.to_string(), path.clone())),
+                None => {}
             }
         }
+        let paths = hashed_paths.into_iter().collect();
 
         let mut file_map = HashMap::new();
         for (index, file) in bundle_index.files.iter().enumerate() {
@@ -55,10 +211,17 @@ impl PoeFS {
         }
 
         Self {
-            source: Box::new(source),
+            source,
             bundle_index,
             paths,
             file_map,
+            extracted,
+            duplicate_paths,
+            hash_collisions,
+            loose_paths: HashSet::new(),
+            bundle_cache: HashMap::new(),
+            bundle_cache_order: VecDeque::new(),
+            bundle_cache_size: DEFAULT_BUNDLE_CACHE_SIZE,
             dat_cache: HashMap::new(),
             txt_cache: HashMap::new(),
             it_cache: HashMap::new(),
@@ -66,57 +229,477 @@ impl PoeFS {
         }
     }
 
-    pub fn get_file(&mut self, path: &str) -> Result<Option<Vec<u8>>, anyhow::Error> {
-        let Some(hash) = self.paths.get(path) else {
-            return Err(anyhow!(io::Error::new(
-                io::ErrorKind::NotFound,
-                "path not found in index bundle",
-            )));
+    /// Returns hash of the path the same way it is hashed in the index bundle, path is
+    /// normalized before hashing
+    pub fn hash_path(path: &str) -> u64 {
+        Self::hash_path_with_seed(path, PATH_HASH_SEED)
+    }
+
+    /// Same as `hash_path` but with custom seed
+    pub fn hash_path_with_seed(path: &str, seed: u64) -> u64 {
+        murmur2::murmur64a(normalize_path(path).as_bytes(), seed)
+    }
+
+    /// Adds files stored outside of bundles, like loose files in `Content.ggpk`, to paths of the
+    /// file system so they can be listed and read same as bundled files. Bundled files take
+    /// precedence over loose files with the same path. Returns number of added paths
+    pub fn include_loose_files(&mut self) -> Result<usize, anyhow::Error> {
+        let mut added = 0;
+        for path in self.source.loose_paths()? {
+            let path = normalize_path(&path);
+            if self.paths.contains_key(&path) {
+                continue;
+            }
+            let hash = Self::hash_path(&path);
+            self.paths.insert(path.clone(), hash);
+            self.loose_paths.insert(path);
+            added += 1;
+        }
+        Ok(added)
+    }
+
+    /// Returns paths that are listed in the index bundle more than once, each repeated occurrence
+    /// is listed once
+    pub fn duplicate_paths(&self) -> &[String] {
+        &self.duplicate_paths
+    }
+
+    /// Returns pairs of different paths that have the same hash, files are looked up by the hash
+    /// so both paths resolve to the same file
+    pub fn hash_collisions(&self) -> &[(String, String)] {
+        &self.hash_collisions
+    }
+
+    /// Returns true if file with the path exists, without reading the file
+    pub fn contains(&self, path: &str) -> bool {
+        let path = normalize_path(path);
+        self.loose_paths.contains(&path)
+            || self
+                .paths
+                .get(&path)
+                .is_some_and(|hash| self.extracted || self.file_map.contains_key(hash))
+    }
+
+    /// Reads and decompresses the file. Path is case insensitive and may start with `/` or use
+    /// backslashes, e.g. `/Data/Mods.dat64` and `data/mods.dat64` are the same file, because
+    /// paths are lowercased before hashing the same way the game does
+    ///
+    /// Returns PathNotFound if the path is not in the index bundle or, for files stored outside
+    /// of bundles, is missing from the source
+    pub fn get_file(&mut self, path: &str) -> Result<Option<Vec<u8>>, PoeFsError> {
+        if self.extracted || self.loose_paths.contains(&normalize_path(path)) {
+            return self.get_extracted_file(path).map(Some);
+        }
+        let record_index = self.file_record_index(path)?;
+        let bundle_index = self.bundle_index.files[record_index].bundle_index;
+        let bundle_name = self.bundle_index.bundles[bundle_index as usize]
+            .name
+            .clone();
+        if !self.bundle_cache.contains_key(&bundle_name) {
+            let (bundle, bundle_data) = self.read_bundle(bundle_index, path)?;
+            let decompress_error = |error| PoeFsError::Decompress {
+                bundle: bundle_name.clone(),
+                path: path.to_string(),
+                error,
+            };
+            let mut c = Cursor::new(bundle_data);
+            if bundle.head_payload.uncompressed_size > self.bundle_cache_size as u64 {
+                // bundle won't be cached, so only blocks containing the file are decompressed
+                let file_record = &self.bundle_index.files[record_index];
+                let file_data = bundle
+                    .data_range(
+                        &mut c,
+                        file_record.file_offset as usize,
+                        file_record.file_size as usize,
+                    )
+                    .map_err(decompress_error)?;
+                return Ok(Some(file_data));
+            }
+            #[cfg(feature = "rayon")]
+            let bundle_uncompressed = bundle.data_parallel(&mut c);
+            #[cfg(not(feature = "rayon"))]
+            let bundle_uncompressed = bundle.data(&mut c);
+            let bundle_uncompressed = bundle_uncompressed.map_err(decompress_error)?;
+            self.cache_bundle(bundle_name.clone(), bundle_uncompressed);
+        }
+        let file_data = self.slice_file(path, record_index, &self.bundle_cache[&bundle_name])?;
+        Ok(Some(file_data))
+    }
+
+    /// Sets maximum total size of decompressed bundles kept by `get_file`, in bytes, so reading
+    /// other files from the same bundle doesn't read and decompress it again. The least recently
+    /// added bundles are dropped when the size is exceeded. Bundles larger than the size are not
+    /// kept at all, only blocks containing the requested file are decompressed for them. Default
+    /// is `DEFAULT_BUNDLE_CACHE_SIZE`, 0 disables caching
+    pub fn set_bundle_cache_size(&mut self, size: usize) {
+        self.bundle_cache_size = size;
+        self.evict_bundles(0);
+    }
+
+    /// Drops all cached decompressed bundles, see `set_bundle_cache_size`
+    pub fn clear_bundle_cache(&mut self) {
+        self.bundle_cache.clear();
+        self.bundle_cache_order.clear();
+    }
+
+    fn cache_bundle(&mut self, name: String, data: Vec<u8>) {
+        self.evict_bundles(data.len());
+        self.bundle_cache_order.push_back(name.clone());
+        self.bundle_cache.insert(name, data);
+    }
+
+    /// Drops the least recently added bundles until `additional` bytes fit into the cache
+    fn evict_bundles(&mut self, additional: usize) {
+        let mut used = self.bundle_cache.values().map(Vec::len).sum::<usize>();
+        while used + additional > self.bundle_cache_size {
+            let Some(name) = self.bundle_cache_order.pop_front() else {
+                break;
+            };
+            if let Some(data) = self.bundle_cache.remove(&name) {
+                used -= data.len();
+            }
+        }
+    }
+
+    /// Returns uncompressed size of the file from the index bundle without reading the file,
+    /// see `file_info`
+    pub fn file_size(&self, path: &str) -> Option<u32> {
+        self.file_info(path).map(|info| info.size)
+    }
+
+    /// Returns location of the file from the index bundle without reading the file. Returns None
+    /// if path doesn't exist or file is not stored in a bundle, like files of `ExtractedSource`
+    /// or loose files
+    pub fn file_info(&self, path: &str) -> Option<FileInfo> {
+        if self.extracted || self.loose_paths.contains(&normalize_path(path)) {
+            return None;
+        }
+        let record_index = self.file_record_index(path).ok()?;
+        let file_record = &self.bundle_index.files[record_index];
+        let bundle = self
+            .bundle_index
+            .bundles
+            .get(file_record.bundle_index as usize)?;
+        Some(FileInfo {
+            bundle: bundle.name.clone(),
+            offset: file_record.file_offset,
+            size: file_record.file_size,
+        })
+    }
+
+    /// Reads multiple files, returned files are in the same order as `paths`
+    ///
+    /// Each bundle is read from the source once and sequentially, since sources are not
+    /// thread-safe, and then bundles are decompressed on multiple threads. Requires `rayon`
+    /// feature
+    #[cfg(feature = "rayon")]
+    pub fn get_files_parallel(&mut self, paths: &[&str]) -> Result<Vec<Vec<u8>>, PoeFsError> {
+        self.get_files_parallel_with_progress(paths, |_, _, _| {})
+    }
+
+    /// Same as `get_files_parallel` but calls `progress` with number of completed files, total
+    /// number of files and path of the completed file as each file finishes decompressing
+    ///
+    /// `progress` is called on the calling thread
+    #[cfg(feature = "rayon")]
+    pub fn get_files_parallel_with_progress(
+        &mut self,
+        paths: &[&str],
+        mut progress: impl FnMut(usize, usize, &str),
+    ) -> Result<Vec<Vec<u8>>, PoeFsError> {
+        let mut files = vec![None; paths.len()];
+        let mut done = 0;
+        // files stored outside of bundles are read right away, others are grouped by bundle
+        let mut record_indices = vec![0; paths.len()];
+        let mut bundle_files: HashMap<u32, Vec<usize>> = HashMap::new();
+        for (position, path) in paths.iter().enumerate() {
+            if self.extracted || self.loose_paths.contains(&normalize_path(path)) {
+                files[position] = Some(self.get_extracted_file(path)?);
+                done += 1;
+                progress(done, paths.len(), path);
+                continue;
+            }
+            let record_index = self.file_record_index(path)?;
+            record_indices[position] = record_index;
+            let bundle_index = self.bundle_index.files[record_index].bundle_index;
+            bundle_files.entry(bundle_index).or_default().push(position);
+        }
+
+        let mut bundles = Vec::with_capacity(bundle_files.len());
+        for (bundle_index, positions) in &bundle_files {
+            let (bundle, bundle_data) = self.read_bundle(*bundle_index, paths[positions[0]])?;
+            bundles.push((*bundle_index, bundle, bundle_data));
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        std::thread::scope(|scope| -> Result<(), PoeFsError> {
+            scope.spawn(move || {
+                bundles.into_par_iter().for_each_with(
+                    sender,
+                    |sender, (bundle_index, bundle, bundle_data)| {
+                        let mut c = Cursor::new(bundle_data);
+                        // receiver is gone only if an error was already returned
+                        let _ = sender.send((bundle_index, bundle.data(&mut c)));
+                    },
+                );
+            });
+            for (bundle_index, uncompressed) in receiver {
+                let positions = &bundle_files[&bundle_index];
+                let uncompressed = uncompressed.map_err(|error| PoeFsError::Decompress {
+                    bundle: self.bundle_index.bundles[bundle_index as usize]
+                        .name
+                        .clone(),
+                    path: paths[positions[0]].to_string(),
+                    error,
+                })?;
+                for &position in positions {
+                    let path = paths[position];
+                    let file_data =
+                        self.slice_file(path, record_indices[position], &uncompressed)?;
+                    files[position] = Some(file_data);
+                    done += 1;
+                    progress(done, paths.len(), path);
+                }
+            }
+            Ok(())
+        })?;
+
+        Ok(files.into_iter().map(Option::unwrap).collect())
+    }
+
+    /// Reads file stored outside of bundles, missing files are PathNotFound like paths missing
+    /// from the index bundle
+    fn get_extracted_file(&mut self, path: &str) -> Result<Vec<u8>, PoeFsError> {
+        self.source
+            .get_extracted_file(path)?
+            .ok_or_else(|| PoeFsError::PathNotFound(path.to_string()))
+    }
+
+    /// Returns index of the file record of the path in the bundle index
+    fn file_record_index(&self, path: &str) -> Result<usize, PoeFsError> {
+        let Some(hash) = self.paths.get(&normalize_path(path)) else {
+            return Err(PoeFsError::PathNotFound(path.to_string()));
         };
         let Some(index) = self.file_map.get(hash) else {
-            return Err(anyhow!(io::Error::new(
-                io::ErrorKind::NotFound,
-                "path hash not found in file map",
-            )));
+            return Err(PoeFsError::HashNotInFileMap {
+                path: path.to_string(),
+                hash: *hash,
+            });
         };
-        let file_record = &self.bundle_index.files[*index];
-        let bundle_record = &self.bundle_index.bundles[file_record.bundle_index as usize];
-        let Some((bundle, bundle_data)) = self
+        Ok(*index)
+    }
+
+    /// Reads compressed bundle from the source, `path` is the file which is read from the bundle
+    fn read_bundle(
+        &mut self,
+        bundle_index: u32,
+        path: &str,
+    ) -> Result<(Bundle, Vec<u8>), PoeFsError> {
+        let Some(bundle_record) = self.bundle_index.bundles.get(bundle_index as usize) else {
+            return Err(PoeFsError::InvalidFileRecord {
+                path: path.to_string(),
+                reason: format!(
+                    "points to bundle {} but index has only {} bundles",
+                    bundle_index,
+                    self.bundle_index.bundles.len()
+                ),
+            });
+        };
+        let Some(bundle) = self
             .source
             .get_file(&format!("/Bundles2/{}.bundle.bin", bundle_record.name))?
         else {
-            return Err(anyhow!(io::Error::new(
-                io::ErrorKind::NotFound,
-                "bundle file not found",
-            )));
+            return Err(PoeFsError::BundleNotFound {
+                bundle: bundle_record.name.clone(),
+                path: path.to_string(),
+            });
         };
-        let mut c = Cursor::new(bundle_data);
-        let bundle_uncompressed = bundle.data(&mut c)?;
+        Ok(bundle)
+    }
+
+    /// Cuts file data out of its uncompressed bundle
+    fn slice_file(
+        &self,
+        path: &str,
+        record_index: usize,
+        bundle_uncompressed: &[u8],
+    ) -> Result<Vec<u8>, PoeFsError> {
+        let file_record = &self.bundle_index.files[record_index];
         let start = file_record.file_offset as usize;
         let end = start + file_record.file_size as usize;
-        let file_data = &bundle_uncompressed[start..end];
-        Ok(Some(file_data.to_vec()))
+        let Some(file_data) = bundle_uncompressed.get(start..end) else {
+            return Err(PoeFsError::InvalidFileRecord {
+                path: path.to_string(),
+                reason: format!(
+                    "spans bytes {}..{} but bundle {} has only {} bytes",
+                    start,
+                    end,
+                    self.bundle_index.bundles[file_record.bundle_index as usize].name,
+                    bundle_uncompressed.len()
+                ),
+            });
+        };
+        Ok(file_data.to_vec())
     }
 
+    /// Returns paths of all files in arbitrary order, use `sorted_paths` for stable order
     pub fn get_paths(&self) -> impl Iterator<Item = &String> {
         self.paths.keys()
     }
 
-    /// Helper function to read a .dat file
+    /// Returns paths of files in the directory and its subdirectories in arbitrary order, e.g.
+    /// `Art/2DItems`. Directory is case insensitive and may end with `/`, empty directory matches
+    /// all files
+    pub fn paths_under(&self, dir: &str) -> impl Iterator<Item = &String> {
+        let mut prefix = normalize_path(dir);
+        if !prefix.is_empty() && !prefix.ends_with('/') {
+            prefix.push('/');
+        }
+        self.paths
+            .keys()
+            .filter(move |path| path.starts_with(&prefix))
+    }
+
+    /// Returns sorted paths matching the pattern, e.g. `Data/*.dat64` or `Art/**/*.dds`. `*`
+    /// matches any characters except `/`, `?` matches a single character except `/` and `**`
+    /// matches any number of directories. Pattern is case insensitive as paths are normalized
+    pub fn glob(&self, pattern: &str) -> Vec<&String> {
+        let regex = glob_to_regex(&normalize_path(pattern));
+        let mut paths = self
+            .paths
+            .keys()
+            .filter(|path| regex.is_match(path))
+            .collect::<Vec<_>>();
+        paths.sort_unstable();
+        paths
+    }
+
+    /// Returns paths of all files sorted lexicographically
+    pub fn sorted_paths(&self) -> Vec<&String> {
+        let mut paths = self.paths.keys().collect::<Vec<_>>();
+        paths.sort_unstable();
+        paths
+    }
+
+    /// Returns sorted names of `.dat64` tables in `data/` directory without extension, tables in
+    /// language subdirectories are not included
+    pub fn list_tables(&self) -> Vec<String> {
+        self.paths
+            .keys()
+            .filter_map(|path| path.strip_prefix("data/"))
+            .filter(|name| !name.contains('/'))
+            .filter_map(|name| name.strip_suffix(".dat64"))
+            .map(str::to_string)
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+
+    /// Helper function to read a .dat or .dat64 file
     pub fn read_dat(&mut self, path: impl AsRef<str>) -> Result<&DatFile, anyhow::Error> {
         if self.dat_cache.contains_key(path.as_ref()) {
             return Ok(self.dat_cache.get(path.as_ref()).unwrap());
         }
         let bytes = self
             .get_file(path.as_ref())?
-            .ok_or(anyhow!("path not found in index bundle",))?;
-        let dat_file = DatFile::new(bytes);
+            .ok_or_else(|| anyhow!("path not found in index bundle: {}", path.as_ref()))?;
+        let format = Path::new(path.as_ref())
+            .extension()
+            .and_then(|ext| DatFormat::from_extension(&ext.to_string_lossy()))
+            .unwrap_or_default();
+        let dat_file = DatFile::with_format(bytes, format);
 
         self.dat_cache.insert(path.as_ref().to_owned(), dat_file);
 
         Ok(self.dat_cache.get(path.as_ref()).unwrap())
     }
 
+    /// Reads a .dat or .dat64 file and parses all rows with its table from the schema, see
+    /// `DatRow::read_to_map`
+    pub fn read_table(
+        &mut self,
+        path: &str,
+        schema: &SchemaFile,
+    ) -> Result<Vec<HashMap<String, DatValue>>, anyhow::Error> {
+        let table = schema
+            .find_table_for_path(path)
+            .ok_or_else(|| anyhow!("table for {} not found in schema", path))?;
+        let dat_file = self.read_dat(path)?;
+        Ok(dat_file.iter_rows_map(&table.columns).collect())
+    }
+
+    /// Reads `.dat64` table by its name, e.g. `Mods`, from the language subdirectory like
+    /// `data/french/mods.dat64`, falling back to `data/mods.dat64` if the table is not localized
+    /// or `lang` is English. See `read_table`
+    pub fn read_table_localized(
+        &mut self,
+        table: &str,
+        lang: &str,
+        schema: &SchemaFile,
+    ) -> Result<Vec<HashMap<String, DatValue>>, anyhow::Error> {
+        let localized_path = format!("data/{}/{}.dat64", lang, table).to_lowercase();
+        let path = if !lang.eq_ignore_ascii_case("English") && self.contains(&localized_path) {
+            localized_path
+        } else {
+            format!("data/{}.dat64", table).to_lowercase()
+        };
+        self.read_table(&path, schema)
+    }
+
+    /// Reads the row of `.dat64` table referenced by `value` of `column` and parses it with the
+    /// columns of the referenced table. For references by row index `value` has to be `ForeignRow`
+    /// or `Row`, for references by column the referenced table is scanned for the first row with
+    /// equal value in that column. Returns None if `value` is null, `column` has no reference or
+    /// referenced row does not exist
+    pub fn resolve_foreign(
+        &mut self,
+        column: &TableColumn,
+        value: &DatValue,
+        schema: &SchemaFile,
+    ) -> Result<Option<Vec<DatValue>>, anyhow::Error> {
+        let Some(reference) = &column.references else {
+            return Ok(None);
+        };
+        if matches!(
+            value,
+            DatValue::ForeignRow { rid: None, .. } | DatValue::Row(None)
+        ) {
+            return Ok(None);
+        }
+        let table_name = match reference {
+            Reference::RefUsingRowIndex { table } | Reference::RefUsingColumn { table, .. } => {
+                table
+            }
+        };
+        let table = schema
+            .find_table(table_name)
+            .ok_or_else(|| anyhow!("table {} not found in schema", table_name))?;
+        let path = format!("data/{}.dat64", table.name).to_lowercase();
+        let dat_file = self.read_dat(&path)?;
+        match reference {
+            Reference::RefUsingRowIndex { .. } => {
+                let rid = match value {
+                    DatValue::ForeignRow { rid: Some(rid), .. } | DatValue::Row(Some(rid)) => *rid,
+                    _ => return Err(anyhow!("{:?} is not a row reference", value)),
+                };
+                Ok(dat_file
+                    .get(rid)
+                    .map(|mut row| row.read_with_schema(&table.columns)))
+            }
+            Reference::RefUsingColumn {
+                column: column_name,
+                ..
+            } => {
+                let (index, _) = table.find_column(column_name).ok_or_else(|| {
+                    anyhow!("column {} not found in table {}", column_name, table.name)
+                })?;
+                Ok(dat_file
+                    .iter_rows_vec(&table.columns)
+                    .find(|values| values[index] == *value))
+            }
+        }
+    }
+
     /// Helper function to read a utf-16 with bom text file
     pub fn read_txt(&mut self, path: impl AsRef<str>) -> Result<String, anyhow::Error> {
         self.read_txt_cache(path, true)
@@ -133,16 +716,8 @@ impl PoeFS {
 
         let bytes = self
             .get_file(path.as_ref())?
-            .ok_or(anyhow!("path not found in index bundle"))?;
-        let mut bytes = bytes.as_slice();
-        if bytes[0] == 0xff && bytes[1] == 0xfe {
-            bytes = &bytes[2..];
-        }
-        let vecu16: Vec<u16> = bytes
-            .chunks_exact(2)
-            .map(|a| u16::from_le_bytes([a[0], a[1]]))
-            .collect();
-        let string = String::from_utf16_lossy(&vecu16);
+            .ok_or_else(|| anyhow!("path not found in index bundle: {}", path.as_ref()))?;
+        let string = decode_utf16(&bytes);
         if add_to_cache {
             self.txt_cache.insert(path.as_ref().to_owned(), string);
             Ok(self.txt_cache.get(path.as_ref()).unwrap().clone())
@@ -163,35 +738,98 @@ impl PoeFS {
     }
 
     /// Helper function to read a .it file and recursively extend it from parent .it file
+    ///
+    /// Returns an error if `extends` chain is cyclic or deeper than `MAX_IT_DEPTH`
     pub fn read_it_recursive(&mut self, path: impl AsRef<str>) -> Result<&ITFile, anyhow::Error> {
-        if self.it_recursive_cache.contains_key(path.as_ref()) {
-            return Ok(self.it_recursive_cache.get(path.as_ref()).unwrap());
+        let mut chain = Vec::new();
+        self.read_it_recursive_inner(path.as_ref(), &mut chain)
+    }
+
+    fn read_it_recursive_inner(
+        &mut self,
+        path: &str,
+        chain: &mut Vec<String>,
+    ) -> Result<&ITFile, anyhow::Error> {
+        if self.it_recursive_cache.contains_key(path) {
+            return Ok(self.it_recursive_cache.get(path).unwrap());
+        }
+        if chain.iter().any(|p| p == path) {
+            return Err(anyhow!(
+                "cyclic .it extends chain: {} -> {}",
+                chain.join(" -> "),
+                path
+            ));
         }
-        let it_file = self.read_it(path.as_ref())?;
+        if chain.len() >= MAX_IT_DEPTH {
+            return Err(anyhow!(
+                ".it extends chain of {} exceeds maximum depth of {}",
+                chain[0],
+                MAX_IT_DEPTH
+            ));
+        }
+        let it_file = self.read_it(path)?;
 
         if it_file.extends == "nothing" {
-            return self.read_it(path.as_ref());
+            return self.read_it(path);
         }
 
         let it_file = it_file.clone();
         let parent_path = format!("{}.it", it_file.extends.to_lowercase());
-        let parent_it = self.read_it_recursive(&parent_path)?;
+        chain.push(path.to_string());
+        let parent_it = self.read_it_recursive_inner(&parent_path, chain)?;
         let it_file = it_file.merge(parent_it.clone());
+        chain.pop();
 
-        self.it_recursive_cache
-            .insert(path.as_ref().to_string(), it_file);
+        self.it_recursive_cache.insert(path.to_string(), it_file);
 
-        let cached = self.it_recursive_cache.get(path.as_ref()).unwrap();
+        let cached = self.it_recursive_cache.get(path).unwrap();
         Ok(cached)
     }
 }
 
+/// Normalizes path to the form paths are stored in the index bundle: lowercase with forward
+/// slashes and without leading slash. The game hashes paths in this form
+fn normalize_path(path: &str) -> String {
+    path.replace('\\', "/")
+        .trim_start_matches('/')
+        .to_lowercase()
+}
+
+/// Converts glob pattern to anchored regex, see `PoeFS::glob`
+fn glob_to_regex(pattern: &str) -> Regex {
+    let mut regex = String::from("^");
+    let mut rest = pattern;
+    while let Some(c) = rest.chars().next() {
+        if let Some(after) = rest.strip_prefix("**/") {
+            regex.push_str("(?:.*/)?");
+            rest = after;
+            continue;
+        }
+        if let Some(after) = rest.strip_prefix("**") {
+            regex.push_str(".*");
+            rest = after;
+            continue;
+        }
+        match c {
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            c => regex.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+        rest = &rest[c.len_utf8()..];
+    }
+    regex.push('$');
+    // everything except wildcards is escaped so the regex is always valid
+    Regex::new(&regex).unwrap()
+}
+
 fn make_paths(reader: &mut Cursor<&[u8]>) -> Result<Vec<String>, io::Error> {
     let mut temp: Vec<String> = Vec::new();
     let mut paths = Vec::new();
     let mut base = false;
     let mut buf = Vec::new();
-    while (reader.position() as usize) < reader.get_ref().len() - 4 {
+    // every record is a 4 byte index followed by null terminated string, except for 0 index which
+    // toggles base mode, so 4 or less remaining bytes can only be a trailing toggle
+    while reader.get_ref().len() - reader.position() as usize > 4 {
         let mut index = reader.read_u32::<LittleEndian>()?;
         if index == 0 {
             base = !base;
@@ -205,7 +843,8 @@ fn make_paths(reader: &mut Cursor<&[u8]>) -> Result<Vec<String>, io::Error> {
 
         buf.clear();
         reader.read_until(0, &mut buf)?;
-        let raw = String::from_utf8(buf.clone()).unwrap();
+        // invalid bytes are replaced so a single bad segment doesn't fail the whole index
+        let raw = String::from_utf8_lossy(&buf);
 
         let string = raw.trim_end_matches('\0');
 
@@ -221,3 +860,354 @@ fn make_paths(reader: &mut Cursor<&[u8]>) -> Result<Vec<String>, io::Error> {
     }
     Ok(paths)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error;
+
+    use crate::test_utils::{
+        bundle, bundle_files, index, memory_source, temp_dir, Bundles, RecordingSource,
+    };
+
+    /// Appends path rep record, index 0 toggles base mode and has no string
+    fn record(payload: &mut Vec<u8>, index: u32, string: &str) {
+        payload.extend(index.to_le_bytes());
+        if index != 0 {
+            payload.extend(string.as_bytes());
+            payload.push(0);
+        }
+    }
+
+    #[test]
+    fn make_paths_emits_final_entry() {
+        let mut payload = Vec::new();
+        record(&mut payload, 0, "");
+        record(&mut payload, 1, "data/");
+        record(&mut payload, 1, "art/");
+        record(&mut payload, 0, "");
+        record(&mut payload, 1, "mods.dat64");
+        record(&mut payload, 2, "a.dds");
+        record(&mut payload, 1, "b");
+        // base entries extend earlier base entries as well
+        let expected = ["data/mods.dat64", "data/art/a.dds", "data/b"];
+        assert_eq!(make_paths(&mut Cursor::new(&payload)).unwrap(), expected);
+
+        // trailing base mode toggle doesn't produce a path
+        record(&mut payload, 0, "");
+        assert_eq!(make_paths(&mut Cursor::new(&payload)).unwrap(), expected);
+    }
+
+    #[test]
+    fn files_of_the_same_bundle_read_bundle_once() {
+        let source = memory_source(&[
+            ("a", &[("data/a.txt", b"first"), ("data/b.txt", b"second")]),
+            ("b", &[("data/c.txt", b"third")]),
+        ]);
+        let (source, requests) = RecordingSource::new(source);
+        let mut fs = PoeFS::try_new(source).unwrap();
+        assert_eq!(fs.get_file("data/a.txt").unwrap().unwrap(), b"first");
+        assert_eq!(fs.get_file("data/b.txt").unwrap().unwrap(), b"second");
+        assert_eq!(fs.get_file("data/c.txt").unwrap().unwrap(), b"third");
+        assert_eq!(
+            *requests.borrow(),
+            [
+                "/Bundles2/_.index.bin",
+                "/Bundles2/a.bundle.bin",
+                "/Bundles2/b.bundle.bin"
+            ]
+        );
+
+        // without cache every file reads its bundle
+        fs.set_bundle_cache_size(0);
+        requests.borrow_mut().clear();
+        fs.get_file("data/a.txt").unwrap();
+        fs.get_file("data/b.txt").unwrap();
+        assert_eq!(
+            *requests.borrow(),
+            ["/Bundles2/a.bundle.bin", "/Bundles2/a.bundle.bin"]
+        );
+    }
+
+    fn paths_fs() -> PoeFS {
+        PoeFS::try_new(memory_source(&[(
+            "a",
+            &[
+                ("data/mods.dat64", b"1"),
+                ("data/french/mods.dat64", b"2"),
+                ("database/x.txt", b"3"),
+                ("art/a.dds", b"4"),
+            ],
+        )]))
+        .unwrap()
+    }
+
+    fn sorted<'a>(paths: impl Iterator<Item = &'a String>) -> Vec<&'a str> {
+        let mut paths: Vec<_> = paths.map(String::as_str).collect();
+        paths.sort_unstable();
+        paths
+    }
+
+    #[test]
+    fn paths_under_matches_whole_directory_names() {
+        let fs = paths_fs();
+        let data = ["data/french/mods.dat64", "data/mods.dat64"];
+        assert_eq!(sorted(fs.paths_under("data")), data);
+        assert_eq!(sorted(fs.paths_under("Data/")), data);
+        assert_eq!(sorted(fs.paths_under("/data")), data);
+        assert_eq!(
+            sorted(fs.paths_under("data/french")),
+            ["data/french/mods.dat64"]
+        );
+        assert_eq!(sorted(fs.paths_under("database")), ["database/x.txt"]);
+        assert_eq!(sorted(fs.paths_under("dat")), Vec::<&str>::new());
+        assert_eq!(sorted(fs.paths_under("")).len(), 4);
+    }
+
+    #[test]
+    fn glob_wildcards() {
+        let star = glob_to_regex("data/*.dat64");
+        assert!(star.is_match("data/mods.dat64"));
+        assert!(!star.is_match("data/french/mods.dat64"));
+        assert!(!star.is_match("data/mods.dat"));
+
+        let question = glob_to_regex("art/?.dds");
+        assert!(question.is_match("art/a.dds"));
+        assert!(!question.is_match("art/ab.dds"));
+        assert!(!question.is_match("art//.dds"));
+
+        let any_depth = glob_to_regex("art/**/*.dds");
+        assert!(any_depth.is_match("art/a.dds"));
+        assert!(any_depth.is_match("art/2ditems/rings/a.dds"));
+        assert!(!any_depth.is_match("data/a.dds"));
+
+        let suffix = glob_to_regex("data/**");
+        assert!(suffix.is_match("data/french/mods.dat64"));
+        assert!(!suffix.is_match("database/x.txt"));
+
+        // regex characters are matched literally
+        assert!(!glob_to_regex("a+b.txt").is_match("aab.txt"));
+        assert_eq!(paths_fs().glob("DATA/*.dat64"), ["data/mods.dat64"]);
+    }
+
+    #[test]
+    fn file_info_comes_from_index() {
+        let fs = PoeFS::try_new(memory_source(&[
+            ("a", &[("data/a.txt", b"first"), ("data/b.txt", b"second")]),
+            ("b", &[("data/c.txt", b"third")]),
+        ]))
+        .unwrap();
+        assert_eq!(
+            fs.file_info("Data/B.txt"),
+            Some(FileInfo {
+                bundle: "a".to_string(),
+                offset: 5,
+                size: 6,
+            })
+        );
+        assert_eq!(
+            fs.file_info("data/c.txt"),
+            Some(FileInfo {
+                bundle: "b".to_string(),
+                offset: 0,
+                size: 5,
+            })
+        );
+        assert_eq!(fs.file_size("data/a.txt"), Some(5));
+        assert_eq!(fs.file_size("data/missing.txt"), None);
+        assert_eq!(fs.file_info("data/missing.txt"), None);
+    }
+
+    #[test]
+    fn paths_are_case_insensitive() {
+        // paths in the index are lowercase
+        let mut fs =
+            PoeFS::try_new(memory_source(&[("a", &[("data/mods.dat64", b"mods")])])).unwrap();
+        for path in [
+            "Data/Mods.dat64",
+            "data/mods.dat64",
+            "/DATA/MODS.DAT64",
+            "data\\Mods.dat64",
+        ] {
+            assert!(fs.contains(path), "{path}");
+            assert_eq!(fs.get_file(path).unwrap().unwrap(), b"mods", "{path}");
+        }
+    }
+
+    /// Source of a single file `data/a.txt` in bundle `a`, `patch_record` can modify bytes of
+    /// its file record in the index: hash, bundle index, offset and size
+    fn single_file_source(patch_record: impl FnOnce(&mut [u8])) -> HashMap<String, Vec<u8>> {
+        let bundles: &Bundles = &[("a", &[("data/a.txt", b"hello")])];
+        let mut files = bundle_files(bundles);
+        let mut index = index(bundles);
+        // bundle count and record of bundle `a`, then file count
+        let record = 4 + (4 + 1 + 4) + 4;
+        patch_record(&mut index[record..record + 20]);
+        files.insert("/Bundles2/_.index.bin".to_string(), bundle(&index));
+        files
+    }
+
+    fn get_file_error(files: HashMap<String, Vec<u8>>) -> PoeFsError {
+        let source = MemorySource::from_bundle_bytes(files).unwrap();
+        let mut fs = PoeFS::try_new(source).unwrap();
+        fs.get_file("data/a.txt").unwrap_err()
+    }
+
+    /// Source that fails to read bundles other than the index
+    struct FailingSource(MemorySource);
+
+    impl FileSource for FailingSource {
+        fn get_file(&mut self, path: &str) -> Result<Option<(Bundle, Vec<u8>)>, anyhow::Error> {
+            if path == "/Bundles2/_.index.bin" {
+                return self.0.get_file(path);
+            }
+            Err(anyhow!("connection reset"))
+        }
+    }
+
+    #[test]
+    fn get_file_errors() {
+        let mut fs = PoeFS::try_new(memory_source(&[("a", &[("data/a.txt", b"hello")])])).unwrap();
+        let error = fs.get_file("data/missing.txt").unwrap_err();
+        assert!(matches!(&error, PoeFsError::PathNotFound(path) if path == "data/missing.txt"));
+        assert!(error.source().is_none());
+
+        let error = get_file_error(single_file_source(|record| record[..8].fill(0)));
+        assert!(
+            matches!(error, PoeFsError::HashNotInFileMap { hash, .. } if hash == PoeFS::hash_path("data/a.txt"))
+        );
+
+        let error = get_file_error(single_file_source(|record| {
+            record[16..].copy_from_slice(&100u32.to_le_bytes())
+        }));
+        assert!(matches!(error, PoeFsError::InvalidFileRecord { .. }));
+
+        let mut files = single_file_source(|_| {});
+        files.remove("/Bundles2/a.bundle.bin");
+        let error = get_file_error(files);
+        assert!(matches!(&error, PoeFsError::BundleNotFound { bundle, .. } if bundle == "a"));
+
+        let mut files = single_file_source(|_| {});
+        let bundle = files.get_mut("/Bundles2/a.bundle.bin").unwrap();
+        bundle.truncate(bundle.len() - 2);
+        let error = get_file_error(files);
+        assert!(matches!(&error, PoeFsError::Decompress { bundle, .. } if bundle == "a"));
+        assert!(error.source().unwrap().is::<io::Error>());
+
+        let source = MemorySource::from_bundle_bytes(single_file_source(|_| {})).unwrap();
+        let mut fs = PoeFS::try_new(FailingSource(source)).unwrap();
+        let error = fs.get_file("data/a.txt").unwrap_err();
+        assert!(matches!(error, PoeFsError::Source(_)));
+        assert_eq!(error.source().unwrap().to_string(), "connection reset");
+    }
+
+    #[test]
+    fn missing_extracted_files_are_path_not_found() {
+        let dir = temp_dir("extracted-missing");
+        std::fs::create_dir_all(dir.join("data")).unwrap();
+        std::fs::write(dir.join("data/a.txt"), b"hello").unwrap();
+        let mut fs = PoeFS::try_new(ExtractedSource::new(&dir).unwrap()).unwrap();
+        assert_eq!(fs.get_file("data/a.txt").unwrap().unwrap(), b"hello");
+        let error = fs.get_file("data/missing.txt").unwrap_err();
+        assert!(matches!(&error, PoeFsError::PathNotFound(path) if path == "data/missing.txt"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn get_files_parallel_errors() {
+        let mut fs = PoeFS::try_new(memory_source(&[("a", &[("data/a.txt", b"hello")])])).unwrap();
+        let error = fs
+            .get_files_parallel(&["data/a.txt", "data/missing.txt"])
+            .unwrap_err();
+        assert!(matches!(&error, PoeFsError::PathNotFound(path) if path == "data/missing.txt"));
+
+        let mut files = single_file_source(|_| {});
+        let bundle = files.get_mut("/Bundles2/a.bundle.bin").unwrap();
+        bundle.truncate(bundle.len() - 2);
+        let source = MemorySource::from_bundle_bytes(files).unwrap();
+        let mut fs = PoeFS::try_new(source).unwrap();
+        let error = fs.get_files_parallel(&["data/a.txt"]).unwrap_err();
+        assert!(
+            matches!(&error, PoeFsError::Decompress { bundle, path, .. } if bundle == "a" && path == "data/a.txt")
+        );
+    }
+
+    /// `.dat64` file from fixed data of all rows, without variable data
+    fn dat64(row_count: u32, fixed: &[u8]) -> Vec<u8> {
+        let mut bytes = row_count.to_le_bytes().to_vec();
+        bytes.extend(fixed);
+        bytes.extend([0xBB; 8]);
+        bytes
+    }
+
+    #[test]
+    fn resolve_foreign_reads_referenced_rows() {
+        const NULL: u64 = 0xfefefefefefefefe;
+        let schema = SchemaFile::from_tables_json(
+            r#"[
+                {"name": "Items", "tags": [], "columns": [
+                    {"name": "Mod", "array": false, "type": "foreignrow", "unique": false,
+                     "localized": false, "references": {"table": "Mods"}},
+                    {"name": "ModKey", "array": false, "type": "i32", "unique": false,
+                     "localized": false, "references": {"table": "Mods", "column": "Key"}}
+                ]},
+                {"name": "Mods", "tags": [], "columns": [
+                    {"name": "Key", "array": false, "type": "i32", "unique": false,
+                     "localized": false},
+                    {"name": "Item", "array": false, "type": "row", "unique": false,
+                     "localized": false, "references": {"table": "Items"}}
+                ]}
+            ]"#,
+        )
+        .unwrap();
+        let mut items = Vec::new();
+        for (rid, key) in [(1u64, 100i32), (NULL, 999)] {
+            items.extend(rid.to_le_bytes());
+            items.extend(NULL.to_le_bytes());
+            items.extend(key.to_le_bytes());
+        }
+        let mut mods = Vec::new();
+        for (key, item) in [(100i32, 1u64), (200, 0)] {
+            mods.extend(key.to_le_bytes());
+            mods.extend(item.to_le_bytes());
+        }
+        let items = dat64(2, &items);
+        let mods = dat64(2, &mods);
+        let mut fs = PoeFS::try_new(memory_source(&[(
+            "data",
+            &[("data/items.dat64", &items), ("data/mods.dat64", &mods)],
+        )]))
+        .unwrap();
+        let items_table = schema.find_table("Items").unwrap();
+        let mods_table = schema.find_table("Mods").unwrap();
+        let mut resolve =
+            |column: &TableColumn, value| fs.resolve_foreign(column, &value, &schema).unwrap();
+        let foreign_row = |rid| DatValue::ForeignRow { rid, unknown: None };
+
+        // by row index, in both directions
+        let item_mod = &items_table.columns[0];
+        assert_eq!(
+            resolve(item_mod, foreign_row(Some(1))),
+            Some(vec![DatValue::I32(200), DatValue::Row(Some(0))])
+        );
+        assert_eq!(
+            resolve(&mods_table.columns[1], DatValue::Row(Some(1))),
+            Some(vec![foreign_row(None), DatValue::I32(999)])
+        );
+        assert_eq!(resolve(item_mod, foreign_row(None)), None);
+        assert_eq!(resolve(item_mod, foreign_row(Some(2))), None);
+
+        // by column value
+        let item_mod_key = &items_table.columns[1];
+        assert_eq!(
+            resolve(item_mod_key, DatValue::I32(100)),
+            Some(vec![DatValue::I32(100), DatValue::Row(Some(1))])
+        );
+        assert_eq!(resolve(item_mod_key, DatValue::I32(999)), None);
+
+        // columns without reference resolve to nothing
+        assert_eq!(resolve(&mods_table.columns[0], DatValue::I32(100)), None);
+    }
+}
@@ -1,39 +1,587 @@
-use std::io::Cursor;
+use std::{
+    fs,
+    io::{self, Cursor},
+    path::{Path, PathBuf},
+};
+
+use anyhow::anyhow;
 
 use crate::bundle::Bundle;
 
 use super::FileSource;
 
 pub struct OnlineSource {
-    patch: String,
+    server: PatchServer,
+    /// Directory of downloaded files of the patch, see `with_cache_dir`
+    cache_dir: Option<PathBuf>,
 }
 
 impl OnlineSource {
+    /// Patch server of the global realm
+    pub const DEFAULT_BASE_URL: &'static str = "https://patch.poecdn.com/";
+    /// Text file containing latest patch version of the global realm
+    pub const LATEST_PATCH_URL: &'static str =
+        "https://raw.githubusercontent.com/poe-tool-dev/latest-patch-version/main/latest.txt";
+
+    /// Creates source downloading files from the patch server of the global realm, see
+    /// `with_base_url`
+    ///
+    /// # Panics:
+    /// If latest patch version can't be downloaded or `patch` is not a patch version, use
+    /// `builder` to get an error instead
     pub fn new(patch: Option<String>) -> Self {
-        let patch = patch.unwrap_or_else(|| Self::get_latest_patch());
-        Self { patch }
+        Self::with_base_url(Self::DEFAULT_BASE_URL.to_string(), patch).unwrap()
+    }
+
+    /// Creates source downloading files from the patch server at `base`, for example a regional
+    /// realm or a local mirror. If `patch` is None latest patch of the global realm is used, use
+    /// `builder` to look it up elsewhere
+    ///
+    /// Returns an error if the patch version is not numbers separated by dots or the latest
+    /// patch version can't be downloaded
+    pub fn with_base_url(base: String, patch: Option<String>) -> Result<Self, anyhow::Error> {
+        let builder = Self::builder().cdn(base);
+        match patch {
+            Some(patch) => builder.patch(patch).build(),
+            None => builder.build(),
+        }
+    }
+
+    /// Enables keeping downloaded files in `dir`, files are stored in a subdirectory named after
+    /// the host of the patch server and patch version, e.g. `patch.poecdn.com/3.25.1.1`, and are
+    /// read from there instead of downloading them again. Files of other patch versions and
+    /// patch servers are kept, use `prune_cache` to remove them. Patch version is validated when
+    /// the source is created, so it can't point outside of `dir`
+    ///
+    /// Returns an error of kind InvalidInput if the patch server URL has no host
+    pub fn with_cache_dir(mut self, dir: impl AsRef<Path>) -> Result<Self, io::Error> {
+        let Some(host) = self.server.host_dir_name() else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("patch server URL {} has no host", self.server.base_url),
+            ));
+        };
+        self.cache_dir = Some(dir.as_ref().join(host).join(&self.server.patch));
+        Ok(self)
+    }
+
+    /// Removes cached files of patch versions other than the current one of this patch server,
+    /// files of other patch servers are kept. Does nothing if cache is not enabled
+    pub fn prune_cache(&self) -> Result<(), io::Error> {
+        let Some(host_dir) = self.cache_dir.as_deref().and_then(Path::parent) else {
+            return Ok(());
+        };
+        let entries = match fs::read_dir(host_dir) {
+            Ok(entries) => entries,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(error) => return Err(error),
+        };
+        for entry in entries {
+            let entry = entry?;
+            let name = entry.file_name();
+            let Some(name) = name.to_str() else {
+                continue;
+            };
+            if name != self.server.patch && is_patch_version(name) && entry.file_type()?.is_dir() {
+                fs::remove_dir_all(entry.path())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns builder for selecting patch version and patch server, e.g.
+    /// `OnlineSource::builder().patch("3.25.1.1").cdn("https://patch-poe2.poecdn.com").build()`
+    pub fn builder() -> OnlineSourceBuilder {
+        OnlineSourceBuilder::default()
+    }
+
+    /// Downloads latest patch version from text file at `url`
+    pub fn get_latest_patch_from(url: &str) -> Result<String, anyhow::Error> {
+        let response = reqwest::blocking::get(url)?;
+        Ok(response.text()?.trim().to_string())
+    }
+
+    /// Patch version files are downloaded for
+    pub fn patch(&self) -> &str {
+        &self.server.patch
     }
 
-    fn get_latest_patch() -> String {
-        let response = reqwest::blocking::get(
-            "https://raw.githubusercontent.com/poe-tool-dev/latest-patch-version/main/latest.txt",
-        )
-        .unwrap();
-        response.text().unwrap()
+    /// URL of the file at `path` of the patch
+    pub fn file_url(&self, path: &str) -> String {
+        self.server.file_url(path)
+    }
+}
+
+/// Patch server and patch version files are downloaded from, shared by `OnlineSource` and
+/// `AsyncOnlineSource`
+#[derive(Debug, Clone)]
+struct PatchServer {
+    /// URL of the patch server ending with `/`
+    base_url: String,
+    patch: String,
+}
+
+impl PatchServer {
+    /// Returns an error if the patch version is not numbers separated by dots, since it is used
+    /// in URLs and as cache directory name
+    fn new(cdn: String, patch: String) -> Result<Self, anyhow::Error> {
+        if !is_patch_version(&patch) {
+            return Err(anyhow!(
+                "invalid patch version {patch:?}, expected numbers separated by dots"
+            ));
+        }
+        let base_url = if cdn.ends_with('/') {
+            cdn
+        } else {
+            format!("{cdn}/")
+        };
+        Ok(Self { base_url, patch })
+    }
+
+    fn file_url(&self, path: &str) -> String {
+        format!("{}{}{}", self.base_url, self.patch, path)
+    }
+
+    /// Cache directory name of the patch server, its host and port if set, e.g.
+    /// `patch.poecdn.com` or `127.0.0.1_8080`. Returns None if the URL has no host
+    fn host_dir_name(&self) -> Option<String> {
+        let url = reqwest::Url::parse(&self.base_url).ok()?;
+        let host = url.host_str()?;
+        let name = match url.port() {
+            Some(port) => format!("{host}_{port}"),
+            None => host.to_string(),
+        };
+        let name: String = name
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        // names like `..` would point outside of the cache directory
+        name.chars()
+            .any(|c| c.is_ascii_alphanumeric())
+            .then_some(name)
+    }
+}
+
+/// Builder of `OnlineSource`, see `OnlineSource::builder`
+#[derive(Debug, Clone, Default)]
+pub struct OnlineSourceBuilder {
+    patch: Option<String>,
+    cdn: Option<String>,
+    latest_patch_url: Option<String>,
+}
+
+impl OnlineSourceBuilder {
+    /// Pins patch version, e.g. `3.25.1.1`, to read files of an older game version. Latest patch
+    /// is downloaded from `latest_patch_url` if not set
+    pub fn patch(mut self, patch: impl Into<String>) -> Self {
+        self.patch = Some(patch.into());
+        self
+    }
+
+    /// Sets patch server, e.g. `https://patch-poe2.poecdn.com` or a regional mirror, defaults to
+    /// `OnlineSource::DEFAULT_BASE_URL`
+    pub fn cdn(mut self, cdn: impl Into<String>) -> Self {
+        self.cdn = Some(cdn.into());
+        self
+    }
+
+    /// Sets text file containing the latest patch version, used when patch is not pinned,
+    /// defaults to `OnlineSource::LATEST_PATCH_URL`
+    pub fn latest_patch_url(mut self, url: impl Into<String>) -> Self {
+        self.latest_patch_url = Some(url.into());
+        self
+    }
+
+    /// Creates the source, downloading latest patch version if patch is not pinned
+    ///
+    /// Returns an error if the patch version is not numbers separated by dots or the latest
+    /// patch version can't be downloaded
+    pub fn build(self) -> Result<OnlineSource, anyhow::Error> {
+        let patch = match &self.patch {
+            Some(patch) => patch.clone(),
+            None => OnlineSource::get_latest_patch_from(self.latest_patch_url_or_default())?,
+        };
+        Ok(OnlineSource {
+            server: self.server(patch)?,
+            cache_dir: None,
+        })
+    }
+
+    /// Same as `build` but creates `AsyncOnlineSource`, downloading latest patch version without
+    /// blocking, requires `async` feature
+    #[cfg(feature = "async")]
+    pub async fn build_async(self) -> Result<AsyncOnlineSource, anyhow::Error> {
+        let patch = match &self.patch {
+            Some(patch) => patch.clone(),
+            None => {
+                AsyncOnlineSource::get_latest_patch_from(self.latest_patch_url_or_default()).await?
+            }
+        };
+        Ok(AsyncOnlineSource {
+            client: reqwest::Client::new(),
+            server: self.server(patch)?,
+        })
+    }
+
+    fn latest_patch_url_or_default(&self) -> &str {
+        self.latest_patch_url
+            .as_deref()
+            .unwrap_or(OnlineSource::LATEST_PATCH_URL)
+    }
+
+    fn server(self, patch: String) -> Result<PatchServer, anyhow::Error> {
+        let cdn = self
+            .cdn
+            .unwrap_or_else(|| OnlineSource::DEFAULT_BASE_URL.to_string());
+        PatchServer::new(cdn, patch)
     }
 }
 
 impl FileSource for OnlineSource {
     fn get_file(&mut self, path: &str) -> Result<Option<(Bundle, Vec<u8>)>, anyhow::Error> {
-        let url = format!("https://patch.poecdn.com/{}{}", self.patch, path);
-        // TODO: return Ok(None) if 404 status code
-        let response = reqwest::blocking::get(url)?;
+        let cache_path = self
+            .cache_dir
+            .as_ref()
+            .map(|dir| dir.join(path.trim_start_matches('/')));
+        if let Some(cache_path) = &cache_path {
+            if cache_path.is_file() {
+                return Ok(Some(split_bundle(&fs::read(cache_path)?)?));
+            }
+        }
+        let response = reqwest::blocking::get(self.file_url(path))?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let response = response.error_for_status()?;
         let content = response.bytes()?;
-        let mut c = Cursor::new(content);
-        let bundle = Bundle::parse(&mut c)?;
-        let position = c.position() as usize;
-        let content = c.into_inner();
-        let bytes = content.into_iter().skip(position).collect::<Vec<_>>();
-        Ok(Some((bundle, bytes)))
+        let file = split_bundle(&content)?;
+        if let Some(cache_path) = &cache_path {
+            write_cache_file(cache_path, &content)?;
+        }
+        Ok(Some(file))
+    }
+}
+
+/// Writes downloaded file into the cache, through a temporary file so an interrupted write
+/// doesn't leave a truncated file that would be read later
+fn write_cache_file(path: &Path, content: &[u8]) -> Result<(), io::Error> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    fs::write(&tmp_path, content)?;
+    fs::rename(&tmp_path, path)
+}
+
+/// Whether the string looks like a patch version, e.g. `3.23.2.5`. Used to validate pinned
+/// patches and to tell which cache directories may be removed by `OnlineSource::prune_cache`
+fn is_patch_version(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .split('.')
+            .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// Same as `OnlineSource` but downloads files without blocking, requires `async` feature. Use
+/// `OnlineSource::builder().build_async()` to select patch version and patch server
+///
+/// Requests are made with a shared `reqwest::Client`, so it must be used from within a tokio
+/// runtime
+#[cfg(feature = "async")]
+pub struct AsyncOnlineSource {
+    client: reqwest::Client,
+    server: PatchServer,
+}
+
+#[cfg(feature = "async")]
+impl AsyncOnlineSource {
+    /// Creates source downloading files of `patch` from the patch server of the global realm,
+    /// use `OnlineSourceBuilder::build_async` to get the latest patch
+    ///
+    /// Returns an error if the patch version is not numbers separated by dots
+    pub fn new(patch: String) -> Result<Self, anyhow::Error> {
+        Self::with_base_url(OnlineSource::DEFAULT_BASE_URL.to_string(), patch)
+    }
+
+    /// Creates source downloading files of `patch` from the patch server at `base`
+    ///
+    /// Returns an error if the patch version is not numbers separated by dots
+    pub fn with_base_url(base: String, patch: String) -> Result<Self, anyhow::Error> {
+        Ok(Self {
+            client: reqwest::Client::new(),
+            server: PatchServer::new(base, patch)?,
+        })
+    }
+
+    /// Downloads latest patch version from text file at `url`
+    pub async fn get_latest_patch_from(url: &str) -> Result<String, anyhow::Error> {
+        let response = reqwest::get(url).await?;
+        Ok(response.text().await?.trim().to_string())
+    }
+
+    /// Patch version files are downloaded for
+    pub fn patch(&self) -> &str {
+        &self.server.patch
+    }
+
+    /// URL of the file at `path` of the patch
+    pub fn file_url(&self, path: &str) -> String {
+        self.server.file_url(path)
+    }
+}
+
+#[cfg(feature = "async")]
+impl super::AsyncFileSource for AsyncOnlineSource {
+    fn get_file(
+        &self,
+        path: &str,
+    ) -> impl std::future::Future<Output = Result<Option<(Bundle, Vec<u8>)>, anyhow::Error>> + Send
+    {
+        let request = self.client.get(self.file_url(path));
+        async move {
+            let response = request.send().await?;
+            if response.status() == reqwest::StatusCode::NOT_FOUND {
+                return Ok(None);
+            }
+            let response = response.error_for_status()?;
+            Ok(Some(split_bundle(&response.bytes().await?)?))
+        }
+    }
+}
+
+/// Parses bundle header of downloaded bundle file, returning it with the rest of the file
+fn split_bundle(content: &[u8]) -> Result<(Bundle, Vec<u8>), anyhow::Error> {
+    let mut c = Cursor::new(content);
+    let bundle = Bundle::parse(&mut c)?;
+    let position = c.position() as usize;
+    Ok((bundle, content[position..].to_vec()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::{
+        poefs::PoeFS,
+        test_utils::{bundle, bundle_files, memory_source, temp_dir, Bundles, MockServer},
+    };
+
+    #[test]
+    fn missing_files_are_none() {
+        let files = [("/1.2.3/Bundles2/a.bundle.bin".to_string(), bundle(b"data"))];
+        let server = MockServer::start(files.into());
+        let mut source =
+            OnlineSource::with_base_url(server.url().to_string(), Some("1.2.3".into())).unwrap();
+        assert!(source.get_file("/Bundles2/a.bundle.bin").unwrap().is_some());
+        assert!(source
+            .get_file("/Bundles2/missing.bundle.bin")
+            .unwrap()
+            .is_none());
+        assert_eq!(
+            server.requests(),
+            [
+                "/1.2.3/Bundles2/a.bundle.bin",
+                "/1.2.3/Bundles2/missing.bundle.bin"
+            ]
+        );
+    }
+
+    #[test]
+    fn online_index_has_same_paths_as_local() {
+        let bundles: &Bundles = &[
+            ("data", &[("data/a.dat64", b"a"), ("data/b.dat64", b"bb")]),
+            ("art", &[("art/c.dds", b"ccc")]),
+        ];
+        let files = bundle_files(bundles)
+            .into_iter()
+            .map(|(path, data)| (format!("/1.2.3{path}"), data))
+            .collect();
+        let server = MockServer::start(files);
+        let online = PoeFS::new(
+            OnlineSource::with_base_url(server.url().to_string(), Some("1.2.3".into())).unwrap(),
+        );
+        let local = PoeFS::new(memory_source(bundles));
+        let online_paths: HashSet<&String> = online.get_paths().collect();
+        let local_paths: HashSet<&String> = local.get_paths().collect();
+        assert_eq!(online_paths.len(), 3);
+        assert_eq!(online_paths, local_paths);
+    }
+
+    #[test]
+    fn cached_files_are_downloaded_once() {
+        let dir = temp_dir("online-cache");
+        let files = [("/1.2.3/Bundles2/a.bundle.bin".to_string(), bundle(b"data"))];
+        let server = MockServer::start(files.into());
+        let source = || {
+            OnlineSource::with_base_url(server.url().to_string(), Some("1.2.3".into()))
+                .unwrap()
+                .with_cache_dir(&dir)
+                .unwrap()
+        };
+        let mut first = source();
+        let (_, downloaded) = first.get_file("/Bundles2/a.bundle.bin").unwrap().unwrap();
+        let (_, cached) = first.get_file("/Bundles2/a.bundle.bin").unwrap().unwrap();
+        let (_, reopened) = source()
+            .get_file("/Bundles2/a.bundle.bin")
+            .unwrap()
+            .unwrap();
+        assert_eq!(cached, downloaded);
+        assert_eq!(reopened, downloaded);
+        let host = server.url().trim_start_matches("http://").replace(':', "_");
+        assert!(dir.join(host).join("1.2.3/Bundles2/a.bundle.bin").is_file());
+        assert_eq!(server.requests(), ["/1.2.3/Bundles2/a.bundle.bin"]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn cache_is_separate_for_patch_servers_and_kept_across_patches() {
+        let dir = temp_dir("online-cache-servers");
+        let path = "/Bundles2/a.bundle.bin";
+        let first = MockServer::start([(format!("/1.2.3{path}"), bundle(b"first"))].into());
+        let second = MockServer::start(
+            [
+                (format!("/1.2.3{path}"), bundle(b"second")),
+                (format!("/1.2.4{path}"), bundle(b"newer")),
+            ]
+            .into(),
+        );
+        let source = |server: &MockServer, patch: &str| {
+            OnlineSource::with_base_url(server.url().to_string(), Some(patch.into()))
+                .unwrap()
+                .with_cache_dir(&dir)
+                .unwrap()
+        };
+        let read = |source: &mut OnlineSource| {
+            let (bundle, payload) = source.get_file(path).unwrap().unwrap();
+            bundle.data(&mut Cursor::new(payload)).unwrap()
+        };
+        assert_eq!(read(&mut source(&first, "1.2.3")), b"first");
+        assert_eq!(read(&mut source(&second, "1.2.3")), b"second");
+        assert_eq!(read(&mut source(&second, "1.2.4")), b"newer");
+        // switching patches back and forth doesn't download files again
+        assert_eq!(read(&mut source(&second, "1.2.3")), b"second");
+        assert_eq!(read(&mut source(&first, "1.2.3")), b"first");
+        assert_eq!(first.requests().len(), 1);
+        assert_eq!(second.requests().len(), 2);
+
+        // pruning removes other patches of the same server only
+        source(&second, "1.2.4").prune_cache().unwrap();
+        assert_eq!(read(&mut source(&first, "1.2.3")), b"first");
+        assert_eq!(read(&mut source(&second, "1.2.4")), b"newer");
+        assert_eq!(read(&mut source(&second, "1.2.3")), b"second");
+        assert_eq!(first.requests().len(), 1);
+        assert_eq!(second.requests().len(), 3);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn with_base_url_rejects_invalid_patch() {
+        for patch in ["../1.2.3", "", "1..2"] {
+            let source = OnlineSource::with_base_url("http://localhost".into(), Some(patch.into()));
+            assert!(source.is_err(), "{patch}");
+        }
+    }
+
+    #[test]
+    fn builder_builds_file_urls() {
+        let source = OnlineSource::builder()
+            .patch("3.25.1.1")
+            .cdn("https://patch-poe2.poecdn.com")
+            .build()
+            .unwrap();
+        assert_eq!(source.patch(), "3.25.1.1");
+        assert_eq!(
+            source.file_url("/Bundles2/_.index.bin"),
+            "https://patch-poe2.poecdn.com/3.25.1.1/Bundles2/_.index.bin"
+        );
+
+        let source = OnlineSource::builder().patch("3.25.1.1").build().unwrap();
+        assert_eq!(
+            source.file_url("/Bundles2/_.index.bin"),
+            "https://patch.poecdn.com/3.25.1.1/Bundles2/_.index.bin"
+        );
+    }
+
+    #[test]
+    fn builder_validates_patch() {
+        for patch in ["", "3.25.x", "3..25", "../3.25", "3.25/"] {
+            assert!(
+                OnlineSource::builder().patch(patch).build().is_err(),
+                "{patch}"
+            );
+        }
+
+        let files = [("/latest.txt".to_string(), b"3.25.1.2\n".to_vec())];
+        let server = MockServer::start(files.into());
+        let source = OnlineSource::builder()
+            .latest_patch_url(format!("{}/latest.txt", server.url()))
+            .build()
+            .unwrap();
+        assert_eq!(source.patch(), "3.25.1.2");
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn async_source_downloads_files() {
+        use crate::poefs::AsyncFileSource;
+
+        let files = [
+            ("/latest.txt".to_string(), b"1.2.3\n".to_vec()),
+            ("/1.2.3/Bundles2/a.bundle.bin".to_string(), bundle(b"data")),
+        ];
+        let server = MockServer::start(files.into());
+        let source = OnlineSource::builder()
+            .cdn(server.url())
+            .latest_patch_url(format!("{}/latest.txt", server.url()))
+            .build_async()
+            .await
+            .unwrap();
+        assert_eq!(source.patch(), "1.2.3");
+        let source = std::sync::Arc::new(source);
+        // futures are Send, so files can be downloaded on other tasks
+        let task_source = source.clone();
+        let (bundle, payload) =
+            tokio::spawn(async move { task_source.get_file("/Bundles2/a.bundle.bin").await })
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap();
+        assert_eq!(bundle.data(&mut Cursor::new(payload)).unwrap(), b"data");
+        assert!(source
+            .get_file("/Bundles2/missing.bundle.bin")
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(
+            server.requests(),
+            [
+                "/latest.txt",
+                "/1.2.3/Bundles2/a.bundle.bin",
+                "/1.2.3/Bundles2/missing.bundle.bin"
+            ]
+        );
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn async_source_validates_patch() {
+        assert!(
+            AsyncOnlineSource::with_base_url("http://localhost".into(), "../1".into()).is_err()
+        );
+        let source = AsyncOnlineSource::new("3.25.1.1".into()).unwrap();
+        assert_eq!(
+            source.file_url("/Bundles2/_.index.bin"),
+            "https://patch.poecdn.com/3.25.1.1/Bundles2/_.index.bin"
+        );
     }
 }
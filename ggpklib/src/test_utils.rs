@@ -0,0 +1,292 @@
+//! Builders of game files for tests
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    io::{BufRead, BufReader, Write},
+    net::TcpListener,
+    rc::Rc,
+    sync::{Arc, Mutex},
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::{
+    bundle::Bundle,
+    ggpk::DirectoryEntry,
+    poefs::{FileSource, MemorySource, PoeFS},
+};
+
+/// Bundle names with paths and content of their files
+pub type Bundles<'a> = [(&'a str, &'a [(&'a str, &'a [u8])])];
+
+/// Uncompressed size of bundle blocks, the largest chunk Oodle can store uncompressed
+pub const GRANULARITY: usize = 0x40000;
+
+/// Builds bundle file storing `data` in uncompressed Kraken blocks of `granularity` bytes
+pub fn bundle_with_granularity(data: &[u8], granularity: usize) -> Vec<u8> {
+    assert!(granularity <= GRANULARITY);
+    let blocks: Vec<Vec<u8>> = data
+        .chunks(granularity)
+        .map(|chunk| {
+            // chunk header: uncompressed flag and Kraken decoder type
+            let mut block = vec![0x4C, 0x06];
+            block.extend(chunk);
+            block
+        })
+        .collect();
+    let payload_size: usize = blocks.iter().map(Vec::len).sum();
+
+    let mut head = Vec::new();
+    head.extend(0u32.to_le_bytes()); // first_file_encode
+    head.extend(0u32.to_le_bytes()); // unk10
+    head.extend((data.len() as u64).to_le_bytes());
+    head.extend((payload_size as u64).to_le_bytes());
+    head.extend((blocks.len() as u32).to_le_bytes());
+    head.extend((granularity as u32).to_le_bytes());
+    head.extend([0; 16]); // unk28
+    for block in &blocks {
+        head.extend((block.len() as u32).to_le_bytes());
+    }
+
+    let mut bytes = Vec::new();
+    bytes.extend((data.len() as u32).to_le_bytes());
+    bytes.extend((payload_size as u32).to_le_bytes());
+    bytes.extend((head.len() as u32).to_le_bytes());
+    bytes.extend(head);
+    bytes.extend(blocks.concat());
+    bytes
+}
+
+/// Builds bundle file storing `data` in uncompressed blocks of `GRANULARITY` bytes
+pub fn bundle(data: &[u8]) -> Vec<u8> {
+    bundle_with_granularity(data, GRANULARITY)
+}
+
+/// Builds decompressed index bundle for files grouped by bundle name, all paths are listed in a
+/// single path rep
+pub fn index(bundles: &Bundles) -> Vec<u8> {
+    let mut index = Vec::new();
+    index.extend((bundles.len() as u32).to_le_bytes());
+    for (name, files) in bundles {
+        index.extend((name.len() as u32).to_le_bytes());
+        index.extend(name.as_bytes());
+        let size: usize = files.iter().map(|(_, data)| data.len()).sum();
+        index.extend((size as u32).to_le_bytes());
+    }
+
+    let file_count: usize = bundles.iter().map(|(_, files)| files.len()).sum();
+    index.extend((file_count as u32).to_le_bytes());
+    let mut path_rep_data = Vec::new();
+    for (bundle_index, (_, files)) in bundles.iter().enumerate() {
+        let mut offset = 0;
+        for (path, data) in *files {
+            index.extend(PoeFS::hash_path(path).to_le_bytes());
+            index.extend((bundle_index as u32).to_le_bytes());
+            index.extend((offset as u32).to_le_bytes());
+            index.extend((data.len() as u32).to_le_bytes());
+            offset += data.len();
+            // with no base entries index 1 refers to nothing, so the string is the whole path
+            path_rep_data.extend(1u32.to_le_bytes());
+            path_rep_data.extend(path.as_bytes());
+            path_rep_data.push(0);
+        }
+    }
+
+    index.extend(1u32.to_le_bytes());
+    index.extend(0u64.to_le_bytes()); // hash
+    index.extend(0u32.to_le_bytes()); // payload_offset
+    index.extend((path_rep_data.len() as u32).to_le_bytes());
+    index.extend((path_rep_data.len() as u32).to_le_bytes());
+    index.extend(bundle(&path_rep_data));
+    index
+}
+
+/// Builds raw bundle files and the index bundle for files grouped by bundle name, keys are paths
+/// like `/Bundles2/_.index.bin`
+pub fn bundle_files(bundles: &Bundles) -> HashMap<String, Vec<u8>> {
+    let mut files = HashMap::new();
+    files.insert("/Bundles2/_.index.bin".to_string(), bundle(&index(bundles)));
+    for (name, bundle_files) in bundles {
+        let data: Vec<u8> = bundle_files
+            .iter()
+            .flat_map(|(_, data)| data.iter().copied())
+            .collect();
+        files.insert(format!("/Bundles2/{name}.bundle.bin"), bundle(&data));
+    }
+    files
+}
+
+/// Memory source with files grouped by bundle name, see `bundle_files`
+pub fn memory_source(bundles: &Bundles) -> MemorySource {
+    MemorySource::from_bundle_bytes(bundle_files(bundles)).unwrap()
+}
+
+/// Source that records paths requested from the wrapped source
+pub struct RecordingSource<S> {
+    inner: S,
+    requests: Rc<RefCell<Vec<String>>>,
+}
+
+impl<S> RecordingSource<S> {
+    /// Wraps the source, returned list is filled with requested paths
+    pub fn new(inner: S) -> (Self, Rc<RefCell<Vec<String>>>) {
+        let requests = Rc::new(RefCell::new(Vec::new()));
+        let source = Self {
+            inner,
+            requests: requests.clone(),
+        };
+        (source, requests)
+    }
+}
+
+impl<S: FileSource> FileSource for RecordingSource<S> {
+    fn get_file(&mut self, path: &str) -> Result<Option<(Bundle, Vec<u8>)>, anyhow::Error> {
+        self.requests.borrow_mut().push(path.to_string());
+        self.inner.get_file(path)
+    }
+}
+
+/// HTTP server on localhost serving files by path and 404 for other paths, runs until the test
+/// process exits
+pub struct MockServer {
+    url: String,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl MockServer {
+    pub fn start(files: HashMap<String, Vec<u8>>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let thread_requests = requests.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else {
+                    continue;
+                };
+                let mut reader = BufReader::new(&mut stream);
+                let mut request_line = String::new();
+                if reader.read_line(&mut request_line).is_err() {
+                    continue;
+                }
+                // skip headers, requests have no body
+                let mut line = String::new();
+                while reader.read_line(&mut line).is_ok_and(|n| n > 0) && line != "\r\n" {
+                    line.clear();
+                }
+                let path = request_line
+                    .split_whitespace()
+                    .nth(1)
+                    .unwrap_or_default()
+                    .to_string();
+                let (status, body) = match files.get(&path) {
+                    Some(body) => ("200 OK", body.as_slice()),
+                    None => ("404 Not Found", &[][..]),
+                };
+                thread_requests.lock().unwrap().push(path);
+                let header = format!(
+                    "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    body.len()
+                );
+                let _ = stream.write_all(header.as_bytes());
+                let _ = stream.write_all(body);
+            }
+        });
+        Self { url, requests }
+    }
+
+    /// Base URL of the server, without trailing slash
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Paths requested so far, in order
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+/// Version of GGPK files built by `ggpk`
+pub const GGPK_VERSION: u32 = 3;
+
+/// Directory or file of GGPK file built by `ggpk`
+pub enum Node<'a> {
+    Dir(&'a str, Vec<Node<'a>>),
+    File(&'a str, &'a [u8]),
+}
+
+/// Builds GGPK file with the root directory containing `nodes` and a single free entry
+pub fn ggpk(nodes: Vec<Node>) -> Vec<u8> {
+    let mut bytes = vec![0; 28];
+    let root = write_node(&mut bytes, &Node::Dir("", nodes));
+    let free = bytes.len() as u64;
+    bytes.extend(16u32.to_le_bytes());
+    bytes.extend(b"FREE");
+    bytes.extend(0u64.to_le_bytes());
+
+    let mut header = 28u32.to_le_bytes().to_vec();
+    header.extend(b"GGPK");
+    header.extend(GGPK_VERSION.to_le_bytes());
+    header.extend(root.to_le_bytes());
+    header.extend(free.to_le_bytes());
+    bytes[..28].copy_from_slice(&header);
+    bytes
+}
+
+/// Appends entry of the node after entries of its children, returns offset of the entry
+fn write_node(bytes: &mut Vec<u8>, node: &Node) -> u64 {
+    let utf16_name = |name: &str| {
+        let mut name: Vec<u8> = name.encode_utf16().flat_map(u16::to_le_bytes).collect();
+        name.extend([0, 0]);
+        name
+    };
+    match node {
+        Node::Dir(name, children) => {
+            let children: Vec<(i32, u64)> = children
+                .iter()
+                .map(|child| {
+                    let child_name = match child {
+                        Node::Dir(name, _) | Node::File(name, _) => name,
+                    };
+                    let hash = DirectoryEntry::name_hash(child_name, GGPK_VERSION);
+                    (hash, write_node(bytes, child))
+                })
+                .collect();
+            let offset = bytes.len() as u64;
+            let name = utf16_name(name);
+            let length = 48 + name.len() + children.len() * 12;
+            bytes.extend((length as u32).to_le_bytes());
+            bytes.extend(b"PDIR");
+            bytes.extend((name.len() as u32 / 2).to_le_bytes());
+            bytes.extend((children.len() as u32).to_le_bytes());
+            bytes.extend([0; 32]);
+            bytes.extend(name);
+            for (hash, child_offset) in children {
+                bytes.extend(hash.to_le_bytes());
+                bytes.extend(child_offset.to_le_bytes());
+            }
+            offset
+        }
+        Node::File(name, data) => {
+            let offset = bytes.len() as u64;
+            let name = utf16_name(name);
+            let length = 44 + name.len() + data.len();
+            bytes.extend((length as u32).to_le_bytes());
+            bytes.extend(b"FILE");
+            bytes.extend((name.len() as u32 / 2).to_le_bytes());
+            bytes.extend(Sha256::digest(data));
+            bytes.extend(name);
+            bytes.extend(*data);
+            offset
+        }
+    }
+}
+
+/// Returns empty directory for the test, unique for the test process
+pub fn temp_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("ggpklib-test-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
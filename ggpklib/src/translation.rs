@@ -1,6 +1,6 @@
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, HashMap, HashSet};
 
-use once_cell::sync::Lazy;
+use once_cell::sync::{Lazy, OnceCell};
 use regex::Regex;
 
 static ROW_REGEX: Lazy<Regex> = Lazy::new(|| {
@@ -15,9 +15,15 @@ static LANG_REGEX: Lazy<Regex> =
     Lazy::new(|| Regex::new(r#"^[\s]*lang "(?P<language>[\w ]+)"[\s]*$"#).unwrap());
 static ROW_COUNT_REGEX: Lazy<Regex> =
     Lazy::new(|| Regex::new(r#"^[\s]*(?P<rows>[0-9]+)[\s]*$"#).unwrap());
+static PLACEHOLDER_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"\{(?P<index>[0-9]*)(?::(?P<format>[^}]*))?\}"#).unwrap());
+
+/// Parsed descriptions by language and stat ids, see `TranslationFile::parse`
+pub type ParsedTranslations<'a> = HashMap<&'a str, BTreeMap<StatKey<'a>, Vec<TranslationRow<'a>>>>;
 
 pub struct TranslationFile {
     file: String,
+    includes: OnceCell<Vec<TranslationFile>>,
 }
 
 #[derive(Debug)]
@@ -32,19 +38,108 @@ enum State {
 impl TranslationFile {
     pub fn new(file: String) -> Self {
         let file = file.trim_start_matches('\u{feff}').to_string();
-        Self { file }
+        Self {
+            file,
+            includes: OnceCell::new(),
+        }
+    }
+
+    /// Returns languages present in the file in order of first appearance without parsing rows,
+    /// English is always first since rows without `lang` directive are English
+    pub fn languages(&self) -> Vec<String> {
+        let mut languages = vec!["English".to_string()];
+        for line in self.file.lines() {
+            let Some(cap) = LANG_REGEX.captures(line) else {
+                continue;
+            };
+            let lang = cap.name("language").unwrap().as_str();
+            if !languages.iter().any(|l| l == lang) {
+                languages.push(lang.to_string());
+            }
+        }
+        languages
+    }
+
+    /// Returns paths of files included with `include "..."` directive, as they are written in
+    /// the file
+    pub fn include_paths(&self) -> impl Iterator<Item = &str> {
+        self.file.lines().filter_map(|line| {
+            DESCRIPTION_REGEX
+                .captures(line.trim())
+                .and_then(|cap| cap.name("include"))
+                .map(|m| m.as_str())
+        })
     }
 
-    pub fn parse(&self) -> HashMap<&str, BTreeMap<StatKey, Vec<TranslationRow>>> {
+    /// Same as `parse` but also parses included files and merges their stats into the result,
+    /// stats from this file take precedence over included ones
+    ///
+    /// `resolve` is called with path of the included file as it is written in the file and
+    /// should return its content, returning None skips the include. A file included again
+    /// further down the include chain, e.g. by a cycle, is skipped
+    ///
+    /// Included files are resolved on the first call and stored with this file, later calls
+    /// reuse them and don't call `resolve`, even if it's a different resolver. Create a new
+    /// `TranslationFile` to resolve includes again
+    pub fn parse_with_resolver<F: FnMut(&str) -> Option<String>>(
+        &self,
+        mut resolve: F,
+    ) -> ParsedTranslations<'_> {
+        let mut visited = HashSet::new();
+        self.parse_with_resolver_inner(&mut resolve, &mut visited)
+    }
+
+    fn parse_with_resolver_inner(
+        &self,
+        resolve: &mut dyn FnMut(&str) -> Option<String>,
+        visited: &mut HashSet<String>,
+    ) -> ParsedTranslations<'_> {
+        let includes = self.includes.get_or_init(|| {
+            self.include_paths()
+                .filter(|path| visited.insert(path.to_string()))
+                .filter_map(&mut *resolve)
+                .map(TranslationFile::new)
+                .collect()
+        });
+
+        let mut map: ParsedTranslations = HashMap::new();
+        for include in includes {
+            for (lang, stats) in include.parse_with_resolver_inner(resolve, visited) {
+                map.entry(lang).or_default().extend(stats);
+            }
+        }
+        for (lang, stats) in self.parse() {
+            map.entry(lang).or_default().extend(stats);
+        }
+        map
+    }
+
+    /// Parses the file, lines that can't be parsed are skipped, use `parse_with_warnings` to get
+    /// them
+    pub fn parse(&self) -> ParsedTranslations<'_> {
+        self.parse_with_warnings().0
+    }
+
+    /// Parses the file and returns warnings for lines that were skipped because they couldn't be
+    /// parsed
+    pub fn parse_with_warnings(&self) -> (ParsedTranslations<'_>, Vec<ParseWarning<'_>>) {
         let mut state = State::Description;
         let mut lang = "English";
         let mut row_count = 0;
         let mut stats_ids = StatKey::Single("");
-        let mut map: HashMap<&str, BTreeMap<StatKey, Vec<TranslationRow>>> = HashMap::new();
-        for line in self.file.lines() {
+        let mut map: ParsedTranslations = HashMap::new();
+        let mut warnings = Vec::new();
+        for (line_index, line) in self.file.lines().enumerate() {
             if line.trim().is_empty() {
                 continue;
             }
+            let mut warn = |reason| {
+                warnings.push(ParseWarning {
+                    line_number: line_index + 1,
+                    line,
+                    reason,
+                })
+            };
             match state {
                 State::Description => {
                     if let Some(cap) = DESCRIPTION_REGEX.captures(line) {
@@ -54,7 +149,11 @@ impl TranslationFile {
                     }
                 }
                 State::Stats => {
-                    let stats = STATS_REGEX.captures(line).unwrap();
+                    let Some(stats) = STATS_REGEX.captures(line) else {
+                        warn("expected stat ids");
+                        state = State::Description;
+                        continue;
+                    };
                     let stats_ids_str = stats.name("stat_ids").unwrap().as_str().trim();
                     if stats_ids_str.split(' ').count() == 1 {
                         stats_ids = StatKey::Single(stats_ids_str);
@@ -70,8 +169,10 @@ impl TranslationFile {
                         lang = new_lang;
                         state = State::RowCount;
                     } else if let Some(cap) = ROW_COUNT_REGEX.captures(line) {
-                        row_count = cap.name("rows").unwrap().as_str().parse().unwrap();
-                        state = State::Rows;
+                        row_count = cap.name("rows").unwrap().as_str().parse().unwrap_or(0);
+                        if row_count > 0 {
+                            state = State::Rows;
+                        }
                     } else if let Some(cap) = DESCRIPTION_REGEX.captures(line) {
                         if cap.name("description").is_some() {
                             state = State::Stats;
@@ -79,13 +180,27 @@ impl TranslationFile {
                     }
                 }
                 State::RowCount => {
-                    let cap = ROW_COUNT_REGEX.captures(line).unwrap();
-                    row_count = cap.name("rows").unwrap().as_str().parse().unwrap();
-                    state = State::Rows;
+                    let Some(cap) = ROW_COUNT_REGEX.captures(line) else {
+                        warn("expected row count");
+                        state = State::Lang;
+                        continue;
+                    };
+                    row_count = cap.name("rows").unwrap().as_str().parse().unwrap_or(0);
+                    state = if row_count > 0 {
+                        State::Rows
+                    } else {
+                        State::Lang
+                    };
                 }
                 State::Rows => {
                     row_count -= 1;
-                    let cap = ROW_REGEX.captures(line).unwrap();
+                    if row_count == 0 {
+                        state = State::Lang;
+                    }
+                    let Some(cap) = ROW_REGEX.captures(line) else {
+                        warn("row doesn't match expected format");
+                        continue;
+                    };
                     let format_string = cap.name("description").unwrap().as_str();
                     let condition = cap.name("minmax").unwrap().as_str().trim();
                     let modifiers = cap.name("quantifier").unwrap().as_str().trim();
@@ -99,22 +214,85 @@ impl TranslationFile {
                         .entry(stats_ids.clone())
                         .or_default()
                         .push(row);
-                    if row_count == 0 {
-                        state = State::Lang;
-                    }
                 }
             }
         }
-        map
+        (map, warnings)
+    }
+
+    /// Returns rows of every description containing the stat in all languages, languages are in
+    /// the same order as returned by `languages`
+    ///
+    /// Rows are returned by value since they are parsed on every call, they borrow from the file
+    pub fn rows_for_stat(&self, stat_id: &str) -> Vec<(String, TranslationRow<'_>)> {
+        let mut map = self.parse();
+        let mut rows = Vec::new();
+        for lang in self.languages() {
+            let Some(stats) = map.remove(lang.as_str()) else {
+                continue;
+            };
+            for (key, stat_rows) in stats {
+                if key.contains(stat_id) {
+                    rows.extend(stat_rows.into_iter().map(|row| (lang.clone(), row)));
+                }
+            }
+        }
+        rows
+    }
+
+    /// Formats stat values into a translated string, see `format_stats`
+    ///
+    /// Takes all stat ids of the description instead of a single stat id, since a stat can be
+    /// described together with other stats. Use `format_stat` for stats described alone
+    ///
+    /// The file is parsed on every call, use `parse` once and `format_stats` to format many stats
+    pub fn format(&self, stat_ids: &[&str], values: &[i32], lang: &str) -> Option<String> {
+        format_stats(&self.parse(), stat_ids, values, lang)
+    }
+
+    /// Formats value of a stat that has a description of its own, see `format`
+    pub fn format_stat(&self, stat_id: &str, values: &[i32], lang: &str) -> Option<String> {
+        self.format(&[stat_id], values, lang)
     }
 }
 
+/// Formats stat values into a translated string using the description of exactly `stat_ids`,
+/// e.g. `["base_minimum_added_fire_damage", "base_maximum_added_fire_damage"]`. A stat can be
+/// part of several descriptions, so all stats of the description have to be given
+///
+/// `values` must be in the same order as `stat_ids`. Returns None if language, description or
+/// matching row for values is not found
+pub fn format_stats(
+    translations: &ParsedTranslations,
+    stat_ids: &[&str],
+    values: &[i32],
+    lang: &str,
+) -> Option<String> {
+    let key = match stat_ids {
+        [stat_id] => StatKey::Single(stat_id),
+        stat_ids => StatKey::Multiple(stat_ids.to_vec()),
+    };
+    let rows = translations.get(lang)?.get(&key)?;
+    let row = rows.iter().find(|row| row.matches(values))?;
+    Some(row.format(values))
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum StatKey<'a> {
     Single(&'a str),
     Multiple(Vec<&'a str>),
 }
 
+impl<'a> StatKey<'a> {
+    /// Returns true if key contains given stat id
+    pub fn contains(&self, stat_id: &str) -> bool {
+        match self {
+            StatKey::Single(s) => *s == stat_id,
+            StatKey::Multiple(v) => v.contains(&stat_id),
+        }
+    }
+}
+
 impl<'a> serde::Serialize for StatKey<'a> {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -127,6 +305,15 @@ impl<'a> serde::Serialize for StatKey<'a> {
     }
 }
 
+/// Line that was skipped during parsing
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct ParseWarning<'a> {
+    /// 1-based line number in the file
+    pub line_number: usize,
+    pub line: &'a str,
+    pub reason: &'static str,
+}
+
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, serde::Serialize)]
 pub struct TranslationRow<'a> {
     pub condition: &'a str,
@@ -134,3 +321,324 @@ pub struct TranslationRow<'a> {
     #[serde(skip_serializing_if = "str::is_empty")]
     pub modifiers: &'a str,
 }
+
+impl<'a> TranslationRow<'a> {
+    /// Parses row condition string into conditions, one per stat
+    pub fn conditions(&self) -> Vec<Condition> {
+        self.condition
+            .split_whitespace()
+            .map(Condition::parse)
+            .collect()
+    }
+
+    /// Returns true if every value satisfies the corresponding condition of the row
+    pub fn matches(&self, values: &[i32]) -> bool {
+        self.conditions()
+            .iter()
+            .zip(values)
+            .all(|(condition, value)| condition.matches(*value as i64))
+    }
+
+    /// Substitutes values into the format string, applying row modifiers to them first
+    pub fn format(&self, values: &[i32]) -> String {
+        let mut values: Vec<(f64, Option<usize>)> =
+            values.iter().map(|v| (*v as f64, None)).collect();
+
+        let mut tokens = self.modifiers.split_whitespace().peekable();
+        while let Some(modifier) = tokens.next() {
+            let Some(index) = tokens.peek().and_then(|t| t.parse::<usize>().ok()) else {
+                continue;
+            };
+            tokens.next();
+            if let Some(value) = index.checked_sub(1).and_then(|i| values.get_mut(i)) {
+                *value = apply_modifier(modifier, value.0);
+            }
+        }
+
+        let mut next_index = 0;
+        PLACEHOLDER_REGEX
+            .replace_all(self.format_string, |cap: &regex::Captures| {
+                let index = match cap.name("index").map(|m| m.as_str()) {
+                    Some(i) if !i.is_empty() => {
+                        // index too large for usize can't refer to a value
+                        let Ok(i) = i.parse() else {
+                            return cap[0].to_string();
+                        };
+                        i
+                    }
+                    _ => {
+                        next_index += 1;
+                        next_index - 1
+                    }
+                };
+                let Some((value, decimals)) = values.get(index) else {
+                    return cap[0].to_string();
+                };
+                let formatted = format_number(*value, *decimals);
+                let with_sign = cap
+                    .name("format")
+                    .is_some_and(|f| f.as_str().starts_with('+'));
+                if with_sign && *value >= 0.0 {
+                    format!("+{formatted}")
+                } else {
+                    formatted
+                }
+            })
+            .to_string()
+    }
+}
+
+/// Condition on a single stat value, bounds are inclusive and None means unbounded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub struct Condition {
+    pub min: Option<i64>,
+    pub max: Option<i64>,
+    pub negate: bool,
+}
+
+impl Condition {
+    /// Parses condition like `#`, `5`, `1|#`, `#|-1` or `!0`
+    pub fn parse(condition: &str) -> Self {
+        let (negate, condition) = match condition.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, condition),
+        };
+        let (min, max) = condition.split_once('|').unwrap_or((condition, condition));
+        Self {
+            min: min.parse().ok(),
+            max: max.parse().ok(),
+            negate,
+        }
+    }
+
+    /// Returns true if value satisfies the condition
+    pub fn matches(&self, value: i64) -> bool {
+        let in_range =
+            (self.min.unwrap_or(i64::MIN)..=self.max.unwrap_or(i64::MAX)).contains(&value);
+        in_range != self.negate
+    }
+}
+
+/// Applies translation modifier to the value, returns new value and number of decimal places
+/// to use when displaying it. Unknown modifiers leave value unchanged
+fn apply_modifier(modifier: &str, value: f64) -> (f64, Option<usize>) {
+    match modifier {
+        "negate" => (-value, None),
+        "negate_and_double" => (-value * 2.0, None),
+        "double" => (value * 2.0, None),
+        "times_twenty" => (value * 20.0, None),
+        "times_one_point_five" => (value * 1.5, None),
+        "plus_two_hundred" => (value + 200.0, None),
+        "30%_of_value" => (value * 0.3, None),
+        "60%_of_value" => (value * 0.6, None),
+        "per_minute_to_per_second" => (value / 60.0, Some(1)),
+        "per_minute_to_per_second_0dp" => (value / 60.0, Some(0)),
+        "per_minute_to_per_second_1dp" => (value / 60.0, Some(1)),
+        "per_minute_to_per_second_2dp" | "per_minute_to_per_second_2dp_if_required" => {
+            (value / 60.0, Some(2))
+        }
+        "milliseconds_to_seconds" => (value / 1000.0, None),
+        "milliseconds_to_seconds_0dp" => (value / 1000.0, Some(0)),
+        "milliseconds_to_seconds_1dp" => (value / 1000.0, Some(1)),
+        "milliseconds_to_seconds_2dp" | "milliseconds_to_seconds_2dp_if_required" => {
+            (value / 1000.0, Some(2))
+        }
+        "deciseconds_to_seconds" => (value / 10.0, None),
+        "divide_by_two_0dp" => (value / 2.0, Some(0)),
+        "divide_by_three" => (value / 3.0, None),
+        "divide_by_five" => (value / 5.0, None),
+        "divide_by_six" => (value / 6.0, None),
+        "divide_by_ten_0dp" => (value / 10.0, Some(0)),
+        "divide_by_ten_1dp" | "divide_by_ten_1dp_if_required" => (value / 10.0, Some(1)),
+        "divide_by_twelve" => (value / 12.0, None),
+        "divide_by_fifteen_0dp" => (value / 15.0, Some(0)),
+        "divide_by_twenty_then_double_0dp" => (value / 20.0 * 2.0, Some(0)),
+        "divide_by_fifty" => (value / 50.0, None),
+        "divide_by_one_hundred" => (value / 100.0, None),
+        "divide_by_one_hundred_2dp" | "divide_by_one_hundred_2dp_if_required" => {
+            (value / 100.0, Some(2))
+        }
+        "divide_by_one_hundred_and_negate" => (-value / 100.0, None),
+        "divide_by_one_thousand" => (value / 1000.0, None),
+        "multiplicative_damage_modifier" => (value + 100.0, None),
+        "multiplicative_permyriad_damage_modifier" => (value / 100.0 + 100.0, None),
+        "old_leech_percent" => (value / 5.0, None),
+        "old_leech_permyriad" => (value / 500.0, None),
+        "locations_to_metres" => (value / 10.0, None),
+        _ => (value, None),
+    }
+}
+
+/// Formats number with given decimal places, or with as few as needed (up to 2) if not specified
+fn format_number(value: f64, decimals: Option<usize>) -> String {
+    match decimals {
+        Some(decimals) => format!("{value:.decimals$}"),
+        None if value.fract() == 0.0 => format!("{value}"),
+        None => format!("{value:.2}")
+            .trim_end_matches('0')
+            .trim_end_matches('.')
+            .to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILE: &str = "\
+description
+\t1 fire_damage
+\t1
+\t\t# \"Adds {0} Fire Damage\"
+description
+\t2 fire_damage cold_damage
+\t2
+\t\t1|# 0 \"Adds {0} Fire Damage\"
+\t\t# # \"Adds {0} Fire and {1} Cold Damage\"
+description
+\t1 regen
+\t1
+\t\t# \"Regenerate {0} Life per second\" per_minute_to_per_second 1
+";
+
+    #[test]
+    fn format_uses_description_of_exactly_given_stats() {
+        let file = TranslationFile::new(FILE.to_string());
+        let parsed = file.parse();
+        assert_eq!(
+            format_stats(&parsed, &["fire_damage"], &[5], "English").as_deref(),
+            Some("Adds 5 Fire Damage")
+        );
+        assert_eq!(
+            format_stats(&parsed, &["fire_damage", "cold_damage"], &[5, 7], "English").as_deref(),
+            Some("Adds 5 Fire and 7 Cold Damage")
+        );
+        assert_eq!(
+            format_stats(&parsed, &["fire_damage", "cold_damage"], &[5, 0], "English").as_deref(),
+            Some("Adds 5 Fire Damage")
+        );
+        assert_eq!(
+            format_stats(&parsed, &["cold_damage"], &[7], "English"),
+            None
+        );
+        assert_eq!(
+            format_stats(&parsed, &["fire_damage"], &[5], "French"),
+            None
+        );
+    }
+
+    #[test]
+    fn format_applies_modifiers() {
+        let file = TranslationFile::new(FILE.to_string());
+        assert_eq!(
+            file.format(&["regen"], &[90], "English").as_deref(),
+            Some("Regenerate 1.5 Life per second")
+        );
+        assert_eq!(
+            file.format_stat("regen", &[90], "English").as_deref(),
+            Some("Regenerate 1.5 Life per second")
+        );
+    }
+
+    #[test]
+    fn included_stats_are_merged_with_file_taking_precedence() {
+        let main = TranslationFile::new(
+            "include \"a.txt\"\ndescription\n\t1 fire_damage\n\t1\n\t\t# \"Main {0}\"\n"
+                .to_string(),
+        );
+        let mut requests = Vec::new();
+        let resolve = |path: &str| {
+            requests.push(path.to_string());
+            match path {
+                "a.txt" => Some(
+                    "include \"b.txt\"\ndescription\n\t1 fire_damage\n\t1\n\t\t# \"A {0}\"\n\
+                     description\n\t1 cold_damage\n\t1\n\t\t# \"A cold {0}\"\n"
+                        .to_string(),
+                ),
+                // cycle back to a.txt is not followed
+                "b.txt" => Some(
+                    "include \"a.txt\"\ndescription\n\t1 regen\n\t1\n\t\t# \"B {0}\"\n".to_string(),
+                ),
+                _ => None,
+            }
+        };
+        let parsed = main.parse_with_resolver(resolve);
+        assert_eq!(requests, ["a.txt", "b.txt"]);
+        let format = |stat| format_stats(&parsed, &[stat], &[1], "English");
+        assert_eq!(format("fire_damage").as_deref(), Some("Main 1"));
+        assert_eq!(format("cold_damage").as_deref(), Some("A cold 1"));
+        assert_eq!(format("regen").as_deref(), Some("B 1"));
+
+        // includes are resolved once
+        let parsed = main.parse_with_resolver(|_| panic!("includes are already resolved"));
+        assert_eq!(parsed["English"].len(), 3);
+    }
+
+    #[test]
+    fn malformed_row_is_skipped_with_warning() {
+        let file = TranslationFile::new(
+            "description\n\t1 fire_damage\n\t2\n\t\tnot a row\n\t\t# \"Adds {0}\"\n\
+             description\n\t1 regen\n\t1\n\t\t# \"Regen {0}\"\n"
+                .to_string(),
+        );
+        let (parsed, warnings) = file.parse_with_warnings();
+        assert_eq!(
+            warnings,
+            [ParseWarning {
+                line_number: 4,
+                line: "\t\tnot a row",
+                reason: "row doesn't match expected format",
+            }]
+        );
+        let english = &parsed["English"];
+        assert_eq!(english[&StatKey::Single("fire_damage")].len(), 1);
+        assert_eq!(
+            format_stats(&parsed, &["fire_damage"], &[1], "English").as_deref(),
+            Some("Adds 1")
+        );
+        assert_eq!(
+            format_stats(&parsed, &["regen"], &[1], "English").as_deref(),
+            Some("Regen 1")
+        );
+    }
+
+    #[test]
+    fn malformed_stats_and_row_count_are_skipped_with_warning() {
+        let file = TranslationFile::new(
+            "description\n\tfire_damage\ndescription\n\t1 regen\n\t1\n\t\t# \"Regen {0}\"\n\
+             \tlang \"French\"\n\tone\n\tlang \"German\"\n\t1\n\t\t# \"Reg {0}\"\n"
+                .to_string(),
+        );
+        let (parsed, warnings) = file.parse_with_warnings();
+        let warnings: Vec<_> = warnings.iter().map(|w| (w.line_number, w.reason)).collect();
+        assert_eq!(
+            warnings,
+            [(2, "expected stat ids"), (8, "expected row count")]
+        );
+        assert_eq!(
+            format_stats(&parsed, &["regen"], &[1], "English").as_deref(),
+            Some("Regen 1")
+        );
+        assert_eq!(
+            format_stats(&parsed, &["regen"], &[1], "German").as_deref(),
+            Some("Reg 1")
+        );
+        assert!(!parsed.contains_key("French"));
+    }
+
+    #[test]
+    fn translation_file_is_sync() {
+        fn assert_sync<T: Send + Sync>() {}
+        assert_sync::<TranslationFile>();
+    }
+
+    #[test]
+    fn format_keeps_placeholders_without_value() {
+        let row = TranslationRow {
+            condition: "#",
+            format_string: "{0} {1} {99999999999999999999}",
+            modifiers: "",
+        };
+        assert_eq!(row.format(&[5]), "5 {1} {99999999999999999999}");
+    }
+}
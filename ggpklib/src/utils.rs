@@ -1,40 +1,256 @@
-use std::io::{self, SeekFrom};
+use std::{
+    io::{self, SeekFrom},
+    path::{Path, PathBuf},
+};
 
 use crate::ggpk::{Entry, EntryData};
 
+/// Decodes UTF-16 text of game files, byte order is taken from the byte order mark which is
+/// stripped, text without the mark is decoded as little-endian
+pub fn decode_utf16(bytes: &[u8]) -> String {
+    let (bytes, from_bytes): (_, fn([u8; 2]) -> u16) = match bytes {
+        [0xfe, 0xff, rest @ ..] => (rest, u16::from_be_bytes),
+        [0xff, 0xfe, rest @ ..] => (rest, u16::from_le_bytes),
+        bytes => (bytes, u16::from_le_bytes),
+    };
+    let vecu16: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|a| from_bytes([a[0], a[1]]))
+        .collect();
+    String::from_utf16_lossy(&vecu16)
+}
+
+/// Entry of GGPK with its children, see `build_tree`
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct TreeNode {
+    /// Name of file or directory, empty for other entries and the root directory
+    pub name: String,
+    pub kind: NodeKind,
+    /// Size of the file, total size of files in the directory or size of the free space
+    pub size: u64,
+    pub children: Vec<TreeNode>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NodeKind {
+    Ggpk { version: u32 },
+    Free,
+    Directory,
+    File,
+}
+
+/// Reads the entry and all entries under it into a tree
+pub fn build_tree(
+    entry: &Entry,
+    reader: &mut (impl io::Read + io::Seek),
+) -> Result<TreeNode, io::Error> {
+    let (name, kind, children) = match &entry.data {
+        EntryData::Free { .. } => {
+            return Ok(TreeNode {
+                name: String::new(),
+                kind: NodeKind::Free,
+                size: entry.length as u64,
+                children: Vec::new(),
+            })
+        }
+        EntryData::File { name, .. } => {
+            return Ok(TreeNode {
+                name: name.clone(),
+                kind: NodeKind::File,
+                size: entry.data_length_left() as u64,
+                children: Vec::new(),
+            })
+        }
+        EntryData::Pdir { name, entries, .. } => (
+            name.clone(),
+            NodeKind::Directory,
+            entries.iter().map(|e| e.offset).collect::<Vec<_>>(),
+        ),
+        EntryData::Ggpk { version, entries } => (
+            String::new(),
+            NodeKind::Ggpk { version: *version },
+            entries.iter().map(|e| e.offset).collect(),
+        ),
+    };
+    let mut nodes = Vec::with_capacity(children.len());
+    for offset in children {
+        reader.seek(SeekFrom::Start(offset))?;
+        let entry = Entry::parse(reader)?;
+        nodes.push(build_tree(&entry, reader)?);
+    }
+    let size = nodes
+        .iter()
+        .filter(|node| node.kind != NodeKind::Free)
+        .map(|node| node.size)
+        .sum();
+    Ok(TreeNode {
+        name,
+        kind,
+        size,
+        children: nodes,
+    })
+}
+
 pub fn print_tree(
     entry: &Entry,
     reader: &mut (impl io::Read + io::Seek),
     indentation: u32,
 ) -> Result<(), io::Error> {
+    print_node(&build_tree(entry, reader)?, indentation);
+    Ok(())
+}
+
+fn print_node(node: &TreeNode, indentation: u32) {
     const INDENT_STR: &str = "│ ";
     let indent = indentation.saturating_sub(1);
     let indent_string = INDENT_STR.repeat(indent as usize);
     print!("{}├─", indent_string);
+    match node.kind {
+        NodeKind::Free => println!("Free size: {}", node.size),
+        NodeKind::Directory => println!("{}", node.name),
+        NodeKind::File => println!("{} size: {}", node.name, node.size),
+        NodeKind::Ggpk { version } => println!("Ggpk version={}", version),
+    }
+    for child in &node.children {
+        print_node(child, indentation + 1);
+    }
+}
+
+/// Writes every file of the entry and its subdirectories to `out_dir` keeping directory
+/// structure, directory entry itself becomes a subdirectory of `out_dir`. For the `Ggpk` entry
+/// both of its children are extracted, so the whole GGPK is written to `out_dir`
+pub fn extract_dir(
+    entry: &Entry,
+    reader: &mut (impl io::Read + io::Seek),
+    out_dir: &Path,
+) -> Result<(), io::Error> {
     match &entry.data {
-        EntryData::Free => println!("Free"),
+        EntryData::Free { .. } => {}
         EntryData::Pdir { name, entries, .. } => {
-            println!("{}", name);
+            let dir = entry_path(out_dir, name)?;
+            std::fs::create_dir_all(&dir)?;
             for entry in entries {
                 reader.seek(SeekFrom::Start(entry.offset))?;
                 let entry = Entry::parse(reader)?;
-                print_tree(&entry, reader, indentation + 1)?;
+                extract_dir(&entry, reader, &dir)?;
             }
         }
         EntryData::File { name, .. } => {
-            println!("{} size: {}", name, entry.data_length_left());
+            let path = entry_path(out_dir, name)?;
+            let mut data = vec![0u8; entry.data_length_left() as usize];
+            reader.read_exact(&mut data)?;
+            std::fs::create_dir_all(out_dir)?;
+            std::fs::write(path, data)?;
         }
-        EntryData::Ggpk { version, entries } => {
-            println!("Ggpk version={}", version);
-
-            reader.seek(SeekFrom::Start(entries[0].offset))?;
-            let entry = Entry::parse(reader)?;
-            print_tree(&entry, reader, indentation + 1)?;
-
-            reader.seek(SeekFrom::Start(entries[1].offset))?;
-            let entry = Entry::parse(reader)?;
-            print_tree(&entry, reader, indentation + 1)?;
+        EntryData::Ggpk { entries, .. } => {
+            for ggpk_entry in entries {
+                reader.seek(SeekFrom::Start(ggpk_entry.offset))?;
+                let entry = Entry::parse(reader)?;
+                extract_dir(&entry, reader, out_dir)?;
+            }
         }
     }
     Ok(())
 }
+
+/// Joins entry name to the directory, names that would escape the directory are rejected
+fn entry_path(dir: &Path, name: &str) -> Result<PathBuf, io::Error> {
+    if name == "." || name == ".." || name.contains(['/', '\\']) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid entry name: {name:?}"),
+        ));
+    }
+    Ok(dir.join(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::test_utils::{ggpk, temp_dir, Node};
+
+    fn sample_ggpk() -> Vec<u8> {
+        ggpk(vec![
+            Node::Dir(
+                "Data",
+                vec![
+                    Node::File("a.txt", b"hello"),
+                    Node::Dir("Sub", vec![Node::File("b.bin", &[1, 2, 3])]),
+                ],
+            ),
+            Node::File("readme.txt", b"x"),
+        ])
+    }
+
+    #[test]
+    fn extract_dir_writes_tree() {
+        let dir = temp_dir("extract");
+        let mut reader = Cursor::new(sample_ggpk());
+        let root = Entry::parse(&mut reader).unwrap();
+        extract_dir(&root, &mut reader, &dir).unwrap();
+        assert_eq!(std::fs::read(dir.join("Data/a.txt")).unwrap(), b"hello");
+        assert_eq!(
+            std::fs::read(dir.join("Data/Sub/b.bin")).unwrap(),
+            [1, 2, 3]
+        );
+        assert_eq!(std::fs::read(dir.join("readme.txt")).unwrap(), b"x");
+
+        let mut reader = Cursor::new(ggpk(vec![Node::File("..", b"escape")]));
+        let root = Entry::parse(&mut reader).unwrap();
+        let error = extract_dir(&root, &mut reader, &dir.join("escape")).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn build_tree_mirrors_entries() {
+        let node = |name: &str, kind, size, children| TreeNode {
+            name: name.to_string(),
+            kind,
+            size,
+            children,
+        };
+        let file = |name: &str, size| node(name, NodeKind::File, size, Vec::new());
+        let mut reader = Cursor::new(sample_ggpk());
+        let root = Entry::parse(&mut reader).unwrap();
+        let tree = build_tree(&root, &mut reader).unwrap();
+        let data = node(
+            "Data",
+            NodeKind::Directory,
+            8,
+            vec![
+                file("a.txt", 5),
+                node("Sub", NodeKind::Directory, 3, vec![file("b.bin", 3)]),
+            ],
+        );
+        let expected = node(
+            "",
+            NodeKind::Ggpk { version: 3 },
+            9,
+            vec![
+                node(
+                    "",
+                    NodeKind::Directory,
+                    9,
+                    vec![data, file("readme.txt", 1)],
+                ),
+                node("", NodeKind::Free, 16, Vec::new()),
+            ],
+        );
+        assert_eq!(tree, expected);
+    }
+
+    #[test]
+    fn decode_utf16_uses_byte_order_mark() {
+        let text = "Ä\r\n𝄞";
+        let le: Vec<u8> = text.encode_utf16().flat_map(u16::to_le_bytes).collect();
+        let be: Vec<u8> = text.encode_utf16().flat_map(u16::to_be_bytes).collect();
+        assert_eq!(decode_utf16(&[[0xff, 0xfe].as_slice(), &le].concat()), text);
+        assert_eq!(decode_utf16(&[[0xfe, 0xff].as_slice(), &be].concat()), text);
+        assert_eq!(decode_utf16(&le), text);
+    }
+}